@@ -8,6 +8,9 @@ pub enum ErrorKind {
     Internal,
     InvalidData,
     ApiError(String),
+    Cancelled,
+    Timeout,
+    InvalidState(crate::ResourceState),
 }
 
 impl From<reqwest::Error> for ErrorKind {
@@ -59,6 +62,11 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    pub fn prefixed(mut self, prefix: impl fmt::Display) -> Error {
+        self.msg = format!("{}: {}", prefix, self.msg);
+        self
+    }
 }
 
 impl fmt::Display for Error {
@@ -73,6 +81,9 @@ impl error::Error for Error {
             ErrorKind::Internal => None,
             ErrorKind::InvalidData => None,
             ErrorKind::ApiError(_) => None,
+            ErrorKind::Cancelled => None,
+            ErrorKind::Timeout => None,
+            ErrorKind::InvalidState(_) => None,
         }
     }
 }
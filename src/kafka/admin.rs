@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+
+use super::lookup::unique;
+use super::{
+    ClusterResponse, CreateClusterRequest, CreateTopicRequest, KafkaService, ReconfigureTopicRequest, ResourceState,
+    TopicResponse,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Handler;
+
+#[derive(Debug, Clone)]
+pub struct WaitConfig {
+    pub timeout: Duration,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(300),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(15),
+        }
+    }
+}
+
+impl Handler<'_> {
+    /// Polls the cluster until it is active. Fails with `ErrorKind::InvalidState` once it is failed or
+    /// being deleted, and with `ErrorKind::Timeout` when it is still not active after the timeout.
+    pub async fn wait_until_cluster_active(&self, id: &str, config: WaitConfig) -> Result<ClusterResponse> {
+        let what = format!("cluster {}", id);
+        wait_until_active(&what, config, || async {
+            let cluster = self.get_cluster(id).await?;
+            Ok((cluster.state.clone(), cluster))
+        })
+        .await
+    }
+
+    pub async fn wait_until_topic_active(&self, id: &str, config: WaitConfig) -> Result<TopicResponse> {
+        let what = format!("topic {}", id);
+        wait_until_active(&what, config, || async {
+            let topic = self.get_topic(id).await?;
+            Ok((topic.state.clone(), topic))
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnsureOutcome {
+    Created,
+    /// Names of the settings that were reconfigured.
+    Updated(Vec<&'static str>),
+    Unchanged,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ensured<T> {
+    pub resource: T,
+    pub outcome: EnsureOutcome,
+}
+
+/// Fails when an existing resource differs in settings that cannot be changed in place.
+pub(crate) fn immutable(what: &str, fields: Vec<&str>) -> Result<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    Err(Error::new(
+        format!(
+            "{} exists with different {}, which cannot be changed",
+            what,
+            fields.join(", ")
+        ),
+        ErrorKind::InvalidData,
+    ))
+}
+
+/// Fails when the existing cluster has another region or multizone setting than it would be created with.
+pub(crate) fn check_cluster(what: &str, cluster: &ClusterResponse, req: &CreateClusterRequest) -> Result<()> {
+    let mut fields = Vec::new();
+    if cluster.region != req.region {
+        fields.push("region");
+    }
+    if cluster.multizone.is_some_and(|multizone| multizone != req.multizone) {
+        fields.push("multizone");
+    }
+    immutable(what, fields)
+}
+
+/// The settings of an existing topic which differ from the request, as (setting, current, wanted).
+pub(crate) struct TopicDiff {
+    pub settings: Vec<(&'static str, i32, i32)>,
+    pub req: ReconfigureTopicRequest,
+}
+
+/// Compares the existing topic with the settings it would be created with. Fails when the partitions or
+/// cleanup policy differ, and returns `None` when nothing needs to be reconfigured.
+pub(crate) fn topic_diff(what: &str, topic: &TopicResponse, req: &CreateTopicRequest) -> Result<Option<TopicDiff>> {
+    let mut fields = Vec::new();
+    if topic.partitions != req.partitions {
+        fields.push("partitions");
+    }
+    if topic.cleanup_policy != req.cleanup_policy.as_str() {
+        fields.push("cleanup policy");
+    }
+    immutable(what, fields)?;
+
+    let differs = |current: i32, wanted: i32| (current != wanted).then_some(wanted);
+    let reconfigure = ReconfigureTopicRequest {
+        retention_time: differs(topic.retention_time, req.retention_time),
+        retention_size: differs(topic.retention_size, req.retention_size),
+        max_message_size: differs(topic.max_message_size, req.max_message_size),
+    };
+    let settings: Vec<(&'static str, i32, i32)> = [
+        ("retention time", topic.retention_time, req.retention_time),
+        ("retention size", topic.retention_size, req.retention_size),
+        ("max message size", topic.max_message_size, req.max_message_size),
+    ]
+    .into_iter()
+    .filter(|(_, current, wanted)| current != wanted)
+    .collect();
+    if settings.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(TopicDiff {
+        settings,
+        req: reconfigure,
+    }))
+}
+
+impl Handler<'_> {
+    /// Creates the cluster unless one with the same name exists.
+    /// Fails when the existing cluster has another region or multizone setting.
+    pub async fn ensure_cluster(&self, req: CreateClusterRequest) -> Result<Ensured<ClusterResponse>> {
+        let clusters = self.list_clusters().await?;
+        let found = clusters.into_iter().filter(|c| c.name == req.name);
+        let cluster = match unique("cluster", &req.name, found, |c| c.cluster_id.as_str())? {
+            Some(cluster) => cluster,
+            None => {
+                let resource = self.create_cluster(req).await?;
+                return Ok(Ensured {
+                    resource,
+                    outcome: EnsureOutcome::Created,
+                });
+            }
+        };
+        check_cluster(&format!("cluster {}", req.name), &cluster, &req)?;
+        Ok(Ensured {
+            resource: cluster,
+            outcome: EnsureOutcome::Unchanged,
+        })
+    }
+
+    /// Creates the topic in the cluster unless one with the same name exists, in which case its retention
+    /// and max message size are reconfigured when they differ. Fails when the partitions or cleanup policy differ.
+    pub async fn ensure_topic(&self, req: CreateTopicRequest) -> Result<Ensured<TopicResponse>> {
+        let topics = self.list_topics(&req.cluster_id).await?;
+        let found = topics.into_iter().filter(|t| t.topic_name == req.name);
+        let topic = match unique("topic", &req.name, found, |t| t.topic_id.as_str())? {
+            Some(topic) => topic,
+            None => {
+                let resource = self.create_topic(req).await?;
+                return Ok(Ensured {
+                    resource,
+                    outcome: EnsureOutcome::Created,
+                });
+            }
+        };
+        let diff = match topic_diff(&format!("topic {}", req.name), &topic, &req)? {
+            Some(diff) => diff,
+            None => {
+                return Ok(Ensured {
+                    resource: topic,
+                    outcome: EnsureOutcome::Unchanged,
+                })
+            }
+        };
+        let updated = diff.settings.iter().map(|(name, _, _)| *name).collect();
+        let resource = self.reconfigure_topic(diff.req, &topic.topic_id).await?;
+        Ok(Ensured {
+            resource,
+            outcome: EnsureOutcome::Updated(updated),
+        })
+    }
+}
+
+/// Polls `get` until it reports the resource active, retrying errors with a growing backoff.
+pub(crate) async fn wait_until_active<T, F, Fut>(what: &str, config: WaitConfig, get: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<(ResourceState, T)>>,
+{
+    let deadline = Instant::now() + config.timeout;
+    let mut backoff = config.min_backoff;
+    loop {
+        // lookups right after creating can fail until the resource is visible, so errors are retried too
+        let last = match get().await {
+            Ok((ResourceState::Active, resource)) => return Ok(resource),
+            Ok((state @ (ResourceState::Failed | ResourceState::Deleting | ResourceState::Deleted), _)) => {
+                return Err(Error::new(
+                    format!("{} is {}", what, state),
+                    ErrorKind::InvalidState(state),
+                ))
+            }
+            Ok((state, _)) => format!("still {}", state),
+            Err(err) => err.to_string(),
+        };
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(Error::new(
+                format!("{} not active after {:?}: {}", what, config.timeout, last),
+                ErrorKind::Timeout,
+            ));
+        }
+        sleep(backoff.min(deadline - now)).await;
+        backoff = backoff.saturating_mul(2).min(config.max_backoff);
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::dead_letter::produce_all;
+use super::{FetchResponse, Header, KafkaService, Message, OffsetSpec, TopicPartition, TopicResponse};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Client;
+
+const ARCHIVE_MAGIC: &[u8; 4] = b"UPKA";
+const ARCHIVE_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// One JSON record per line.
+    Ndjson,
+    /// Length-prefixed records after a magic and version header.
+    Binary,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveRecord {
+    pub topic: String,
+    pub partition: u64,
+    pub offset: u64,
+    pub key: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<Header>,
+}
+
+impl From<FetchResponse> for ArchiveRecord {
+    fn from(record: FetchResponse) -> Self {
+        Self {
+            topic: record.topic,
+            partition: record.partition,
+            offset: record.offset,
+            key: record.key,
+            value: record.value,
+            headers: record.headers,
+        }
+    }
+}
+
+fn io_error(err: impl std::fmt::Display) -> Error {
+    Error::new(format!("archive io failed: {}", err), ErrorKind::Internal)
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::new(msg, ErrorKind::InvalidData)
+}
+
+pub struct ArchiveWriter<W: Write> {
+    inner: W,
+    format: ArchiveFormat,
+    started: bool,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub fn new(inner: W, format: ArchiveFormat) -> Self {
+        Self {
+            inner,
+            format,
+            started: false,
+        }
+    }
+
+    pub fn write(&mut self, record: &ArchiveRecord) -> Result<()> {
+        match self.format {
+            ArchiveFormat::Ndjson => {
+                serde_json::to_writer(&mut self.inner, record).map_err(io_error)?;
+                self.inner.write_all(b"\n").map_err(io_error)
+            }
+            ArchiveFormat::Binary => {
+                self.start()?;
+                let mut buf = Vec::new();
+                put_bytes(&mut buf, record.topic.as_bytes());
+                buf.extend_from_slice(&record.partition.to_be_bytes());
+                buf.extend_from_slice(&record.offset.to_be_bytes());
+                put_bytes(&mut buf, record.key.as_bytes());
+                put_bytes(&mut buf, record.value.as_bytes());
+                buf.extend_from_slice(&(record.headers.len() as u32).to_be_bytes());
+                for header in &record.headers {
+                    put_bytes(&mut buf, header.key.as_bytes());
+                    put_bytes(&mut buf, header.value.as_bytes());
+                }
+                self.inner.write_all(&buf).map_err(io_error)
+            }
+        }
+    }
+
+    /// Writes the binary header of an empty archive and flushes.
+    pub fn finish(mut self) -> Result<W> {
+        if self.format == ArchiveFormat::Binary {
+            self.start()?;
+        }
+        self.inner.flush().map_err(io_error)?;
+        Ok(self.inner)
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            self.inner.write_all(ARCHIVE_MAGIC).map_err(io_error)?;
+            self.inner.write_all(&[ARCHIVE_VERSION]).map_err(io_error)?;
+        }
+        Ok(())
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+pub struct ArchiveReader<R: Read> {
+    inner: BufReader<R>,
+    format: ArchiveFormat,
+    started: bool,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    pub fn new(inner: R, format: ArchiveFormat) -> Self {
+        Self {
+            inner: BufReader::new(inner),
+            format,
+            started: false,
+        }
+    }
+
+    fn read_ndjson(&mut self) -> Result<Option<ArchiveRecord>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.inner.read_line(&mut line).map_err(io_error)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                let record =
+                    serde_json::from_str(&line).map_err(|err| invalid(format!("invalid archive record: {}", err)))?;
+                return Ok(Some(record));
+            }
+        }
+    }
+
+    fn read_binary(&mut self) -> Result<Option<ArchiveRecord>> {
+        if !self.started {
+            self.started = true;
+            // an empty input is an empty archive
+            if self.inner.fill_buf().map_err(io_error)?.is_empty() {
+                return Ok(None);
+            }
+            let mut header = [0u8; 5];
+            self.inner.read_exact(&mut header).map_err(io_error)?;
+            if &header[..4] != ARCHIVE_MAGIC || header[4] != ARCHIVE_VERSION {
+                return Err(invalid("not an upstash kafka archive"));
+            }
+        }
+        if self.inner.fill_buf().map_err(io_error)?.is_empty() {
+            return Ok(None);
+        }
+        let topic = self.string()?;
+        let partition = u64::from_be_bytes(self.array()?);
+        let offset = u64::from_be_bytes(self.array()?);
+        let key = self.string()?;
+        let value = self.string()?;
+        let count = u32::from_be_bytes(self.array()?);
+        let mut headers = Vec::new();
+        for _ in 0..count {
+            headers.push(Header::new(self.string()?, self.string()?));
+        }
+        Ok(Some(ArchiveRecord {
+            topic,
+            partition,
+            offset,
+            key,
+            value,
+            headers,
+        }))
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|_| invalid("truncated archive record"))?;
+        Ok(buf)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = u32::from_be_bytes(self.array()?) as usize;
+        let mut buf = vec![0u8; len];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|_| invalid("truncated archive record"))?;
+        String::from_utf8(buf).map_err(|err| invalid(format!("invalid archive record: {}", err)))
+    }
+}
+
+impl<R: Read> Iterator for ArchiveReader<R> {
+    type Item = Result<ArchiveRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.format {
+            ArchiveFormat::Ndjson => self.read_ndjson(),
+            ArchiveFormat::Binary => self.read_binary(),
+        };
+        record.transpose()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Earliest,
+    Latest,
+    Offset(u64),
+    /// Unix timestamp in milliseconds.
+    Timestamp(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportRange {
+    pub topic: String,
+    pub partitions: Vec<u64>,
+    pub start: Bound,
+    /// Exclusive; `Latest` exports everything up to the time of the call.
+    pub end: Bound,
+}
+
+impl ExportRange {
+    pub fn new(topic: impl Into<String>, partitions: impl IntoIterator<Item = u64>) -> Self {
+        Self {
+            topic: topic.into(),
+            partitions: partitions.into_iter().collect(),
+            start: Bound::Earliest,
+            end: Bound::Latest,
+        }
+    }
+
+    /// Every partition of the topic.
+    pub fn all(topic: &TopicResponse) -> Self {
+        Self::new(&topic.topic_name, 0..topic.partitions as u64)
+    }
+
+    pub fn between(mut self, start: Bound, end: Bound) -> Self {
+        self.start = start;
+        self.end = end;
+        self
+    }
+}
+
+impl Client {
+    async fn resolve(&self, topic: &str, partitions: &[u64], bound: Bound) -> Result<BTreeMap<u64, u64>> {
+        let spec = match bound {
+            Bound::Offset(offset) => return Ok(partitions.iter().map(|p| (*p, offset)).collect()),
+            Bound::Earliest => OffsetSpec::Earliest,
+            Bound::Latest => OffsetSpec::Latest,
+            Bound::Timestamp(timestamp) => OffsetSpec::Timestamp(timestamp),
+        };
+        let req = partitions
+            .iter()
+            .map(|p| TopicPartition::new(topic, *p))
+            .collect::<Vec<_>>();
+        let handler = self.handler("");
+        let mut offsets = BTreeMap::new();
+        let mut missing = Vec::new();
+        for offset in handler.partition_offsets(spec, req).await? {
+            match u64::try_from(offset.offset) {
+                Ok(value) => {
+                    offsets.insert(offset.partition, value);
+                }
+                Err(_) => missing.push(TopicPartition::new(topic, offset.partition)),
+            }
+        }
+        // no record at or after the timestamp
+        if !missing.is_empty() {
+            for offset in handler.partition_offsets(OffsetSpec::Latest, missing).await? {
+                offsets.insert(offset.partition, u64::try_from(offset.offset).unwrap_or_default());
+            }
+        }
+        Ok(offsets)
+    }
+
+    /// Writes the range partition by partition in offset order, returning the number of records exported.
+    pub async fn export_topic(&self, range: &ExportRange, writer: &mut ArchiveWriter<impl Write>) -> Result<u64> {
+        let start = self.resolve(&range.topic, &range.partitions, range.start).await?;
+        let end = self.resolve(&range.topic, &range.partitions, range.end).await?;
+        let fetcher = self.fetcher();
+        let mut count = 0;
+        for partition in &range.partitions {
+            let (start, end) = match (start.get(partition), end.get(partition)) {
+                (Some(start), Some(end)) => (*start, *end),
+                _ => return Err(invalid(format!("no offsets for {}/{}", range.topic, partition))),
+            };
+            fetcher
+                .fetch_range_pages(&range.topic, *partition, start, end, |page| {
+                    for record in page {
+                        writer.write(&record.into())?;
+                        count += 1;
+                    }
+                    Ok(())
+                })
+                .await?;
+        }
+        Ok(count)
+    }
+
+    /// Produces the archived records to `topic` in archive order and on their original partitions,
+    /// keeping keys and headers. Returns the number of records imported.
+    pub async fn import_topic(&self, topic: &str, reader: ArchiveReader<impl Read>, batch_size: usize) -> Result<u64> {
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut count = 0;
+        for record in reader {
+            let record = record?;
+            let mut message = Message::new(topic, record.value, Some(record.partition), Some(record.key));
+            message.headers = record.headers;
+            batch.push(message);
+            if batch.len() >= batch_size.max(1) {
+                count += batch.len() as u64;
+                produce_all(self, std::mem::take(&mut batch), topic).await?;
+            }
+        }
+        if !batch.is_empty() {
+            count += batch.len() as u64;
+            produce_all(self, batch, topic).await?;
+        }
+        Ok(count)
+    }
+}
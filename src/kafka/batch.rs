@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Instant};
+
+use super::{Message, ProduceResponse, TopicResponse};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Client;
+
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    pub max_messages: usize,
+    pub max_bytes: usize,
+    pub linger: Duration,
+    pub max_message_size: Option<usize>,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_messages: 100,
+            max_bytes: 1048576,
+            linger: Duration::from_millis(10),
+            max_message_size: None,
+        }
+    }
+}
+
+impl BatchConfig {
+    pub fn for_topic(topic: &TopicResponse) -> Self {
+        Self {
+            max_message_size: usize::try_from(topic.max_message_size).ok(),
+            ..Default::default()
+        }
+    }
+}
+
+type Delivery = oneshot::Sender<Result<ProduceResponse>>;
+
+enum Command {
+    Send(Message, Delivery),
+    Flush(oneshot::Sender<()>),
+}
+
+pub struct DeliveryFuture(oneshot::Receiver<Result<ProduceResponse>>);
+
+impl Future for DeliveryFuture {
+    type Output = Result<ProduceResponse>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|_| Err(Error::new("batching producer stopped", ErrorKind::Internal))))
+    }
+}
+
+pub struct BatchingProducer {
+    sender: mpsc::UnboundedSender<Command>,
+    task: JoinHandle<()>,
+}
+
+impl BatchingProducer {
+    /// Spawns the background flush task on the current Tokio runtime.
+    /// Panics when called outside a runtime, use `new_in` with a runtime handle there.
+    pub fn new(client: Client, config: BatchConfig) -> Self {
+        Self::new_in(client, config, &Handle::current())
+    }
+
+    pub fn new_in(client: Client, config: BatchConfig, runtime: &Handle) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = runtime.spawn(run(client, config, receiver));
+        Self { sender, task }
+    }
+
+    pub fn send(&self, message: Message) -> DeliveryFuture {
+        let (tx, rx) = oneshot::channel();
+        if let Err(mpsc::error::SendError(Command::Send(_, tx))) = self.sender.send(Command::Send(message, tx)) {
+            let _ = tx.send(Err(Error::new("batching producer stopped", ErrorKind::Internal)));
+        }
+        DeliveryFuture(rx)
+    }
+
+    pub async fn flush(&self) {
+        let (tx, rx) = oneshot::channel();
+        if self.sender.send(Command::Flush(tx)).is_ok() {
+            let _ = rx.await;
+        }
+    }
+
+    /// Stops accepting messages and waits until everything buffered has been produced.
+    pub async fn shutdown(self) {
+        drop(self.sender);
+        let _ = self.task.await;
+    }
+}
+
+/// Size of the record as the broker stores it, the topic is not part of it.
+pub(crate) fn message_size(message: &Message) -> usize {
+    let headers: usize = message.headers.iter().map(|h| h.key.len() + h.value.len()).sum();
+    message.key.len() + message.value.len() + headers
+}
+
+/// The message as it will be sent, validated and compressed as configured on the client.
+fn prepared(client: &Client, message: Message) -> Result<Message> {
+    let owned = match client.producer().prepare(&message)? {
+        Cow::Owned(prepared) => Some(prepared),
+        Cow::Borrowed(_) => None,
+    };
+    Ok(owned.unwrap_or(message))
+}
+
+struct Batch {
+    items: Vec<(Message, Delivery)>,
+    bytes: usize,
+    deadline: Option<Instant>,
+}
+
+impl Batch {
+    fn push(&mut self, message: Message, delivery: Delivery, linger: Duration) {
+        self.bytes += message_size(&message);
+        self.items.push((message, delivery));
+        self.deadline.get_or_insert_with(|| Instant::now() + linger);
+    }
+
+    async fn flush(&mut self, client: &Client) {
+        self.bytes = 0;
+        self.deadline = None;
+        if self.items.is_empty() {
+            return;
+        }
+        let (messages, deliveries): (Vec<_>, Vec<_>) = std::mem::take(&mut self.items).into_iter().unzip();
+        match client.producer().produce_prepared(messages).await {
+            Ok(report) => {
+                for (delivery, produced) in deliveries.into_iter().zip(report.results) {
+                    let _ = delivery.send(produced.result);
+                }
+            }
+            Err(err) => {
+                for delivery in deliveries {
+                    let _ = delivery.send(Err(Error::new(err.to_string(), ErrorKind::ApiError(err.to_string()))));
+                }
+            }
+        }
+    }
+}
+
+async fn run(client: Client, config: BatchConfig, mut receiver: mpsc::UnboundedReceiver<Command>) {
+    let mut batch = Batch {
+        items: Vec::new(),
+        bytes: 0,
+        deadline: None,
+    };
+    loop {
+        let command = match batch.deadline {
+            Some(deadline) => tokio::select! {
+                command = receiver.recv() => command,
+                _ = sleep_until(deadline) => {
+                    batch.flush(&client).await;
+                    continue;
+                }
+            },
+            None => receiver.recv().await,
+        };
+        match command {
+            Some(Command::Send(message, delivery)) => {
+                let message = match prepared(&client, message) {
+                    Ok(message) => message,
+                    Err(err) => {
+                        let _ = delivery.send(Err(err));
+                        continue;
+                    }
+                };
+                let size = message_size(&message);
+                if let Some(max) = config.max_message_size {
+                    if size > max {
+                        let msg = format!("message of {} bytes exceeds max message size {}", size, max);
+                        let _ = delivery.send(Err(Error::new(msg, ErrorKind::InvalidData)));
+                        continue;
+                    }
+                }
+                if !batch.items.is_empty() && batch.bytes + size > config.max_bytes {
+                    batch.flush(&client).await;
+                }
+                batch.push(message, delivery, config.linger);
+                if batch.items.len() >= config.max_messages || batch.bytes >= config.max_bytes {
+                    batch.flush(&client).await;
+                }
+            }
+            Some(Command::Flush(done)) => {
+                batch.flush(&client).await;
+                let _ = done.send(());
+            }
+            None => {
+                batch.flush(&client).await;
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::Write;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::write::{GzDecoder, GzEncoder};
+
+use super::{Header, Message};
+use crate::error::{Error, ErrorKind, Result};
+
+pub const COMPRESSION_HEADER: &str = "upstash-compression";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+impl Codec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+            Codec::Lz4 => "lz4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gzip" => Some(Codec::Gzip),
+            "zstd" => Some(Codec::Zstd),
+            "lz4" => Some(Codec::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let compressed = match self {
+            Codec::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).and_then(|_| encoder.finish())
+            }
+            Codec::Zstd => zstd::encode_all(data, 0),
+            Codec::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        };
+        compressed.map_err(|err| {
+            Error::new(
+                format!("{} compression failed: {}", self.name(), err),
+                ErrorKind::Internal,
+            )
+        })
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let decompressed = match self {
+            Codec::Gzip => {
+                let mut decoder = GzDecoder::new(Vec::new());
+                decoder.write_all(data).and_then(|_| decoder.finish())
+            }
+            Codec::Zstd => zstd::decode_all(data),
+            Codec::Lz4 => lz4_flex::decompress_size_prepended(data).map_err(std::io::Error::other),
+        };
+        decompressed.map_err(|err| {
+            Error::new(
+                format!("{} decompression failed: {}", self.name(), err),
+                ErrorKind::InvalidData,
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Compression {
+    pub codec: Codec,
+    pub min_size: usize,
+}
+
+impl Compression {
+    pub fn new(codec: Codec) -> Self {
+        Self { codec, min_size: 1024 }
+    }
+
+    /// Values shorter than `min_size`, or which would not get smaller, are sent as they are.
+    pub fn compress_message(&self, message: &Message) -> Result<Message> {
+        let mut message = message.clone();
+        if message.value.len() < self.min_size {
+            return Ok(message);
+        }
+        let encoded = STANDARD.encode(self.codec.compress(message.value.as_bytes())?);
+        if encoded.len() < message.value.len() {
+            message.value = encoded;
+            message.headers.push(Header::new(COMPRESSION_HEADER, self.codec.name()));
+        }
+        Ok(message)
+    }
+}
+
+/// Restores a value compressed by the producer, dropping the codec header.
+pub fn decompress_value(headers: &mut Vec<Header>, value: &mut String) -> Result<()> {
+    let position = match headers.iter().position(|h| h.key == COMPRESSION_HEADER) {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+    let codec = Codec::from_name(&headers[position].value).ok_or_else(|| {
+        Error::new(
+            format!("unknown compression codec {}", headers[position].value),
+            ErrorKind::InvalidData,
+        )
+    })?;
+    let data = STANDARD
+        .decode(value.as_bytes())
+        .map_err(|err| Error::new(format!("invalid compressed value: {}", err), ErrorKind::InvalidData))?;
+    let data = codec.decompress(&data)?;
+    *value = String::from_utf8(data)
+        .map_err(|err| Error::new(format!("invalid compressed value: {}", err), ErrorKind::InvalidData))?;
+    headers.remove(position);
+    Ok(())
+}
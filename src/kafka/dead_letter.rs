@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::time::sleep;
+
+use super::{ConsumeResponse, KafkaService, Message};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Client;
+
+pub const DLQ_ERROR_HEADER: &str = "dlq-error";
+pub const DLQ_ATTEMPTS_HEADER: &str = "dlq-attempts";
+pub const DLQ_SOURCE_TOPIC_HEADER: &str = "dlq-source-topic";
+pub const DLQ_SOURCE_PARTITION_HEADER: &str = "dlq-source-partition";
+pub const DLQ_SOURCE_OFFSET_HEADER: &str = "dlq-source-offset";
+
+#[derive(Debug, Clone)]
+pub struct DeadLetterPolicy {
+    pub topic: String,
+    pub max_attempts: u32,
+    pub retry_backoff: Duration,
+}
+
+impl DeadLetterPolicy {
+    pub fn new(topic: impl Into<String>, max_attempts: u32) -> Self {
+        Self {
+            topic: topic.into(),
+            max_attempts,
+            retry_backoff: Duration::from_millis(500),
+        }
+    }
+
+    pub async fn send(&self, client: &Client, records: &[ConsumeResponse], err: &Error, attempts: u32) -> Result<()> {
+        let messages = records
+            .iter()
+            .map(|record| dead_letter_message(&self.topic, record, err, attempts))
+            .collect();
+        produce_all(client, messages, &self.topic).await
+    }
+}
+
+/// Copies the record into a message for the dead-letter topic, keeping its key and headers.
+pub fn dead_letter_message(topic: &str, record: &ConsumeResponse, err: &Error, attempts: u32) -> Message {
+    let mut message = Message::new(topic, &record.value, None, Some(&record.key));
+    message.headers = record.headers.clone();
+    message
+        .with_header(DLQ_ERROR_HEADER, err.to_string())
+        .with_header(DLQ_ATTEMPTS_HEADER, attempts.to_string())
+        .with_header(DLQ_SOURCE_TOPIC_HEADER, &record.topic)
+        .with_header(DLQ_SOURCE_PARTITION_HEADER, record.partition.to_string())
+        .with_header(DLQ_SOURCE_OFFSET_HEADER, record.offset.to_string())
+}
+
+pub(crate) async fn produce_all(client: &Client, messages: Vec<Message>, topic: &str) -> Result<()> {
+    let report = client.producer().produce_report(messages).await?;
+    if let Some((_, err)) = report.failed().next() {
+        return Err(Error::new(
+            format!("produce to {} failed: {}", topic, err),
+            ErrorKind::ApiError(err.to_string()),
+        ));
+    }
+    Ok(())
+}
+
+/// Runs the attempt until it succeeds or the policy gives up, returning the last error and attempt count.
+pub(crate) async fn attempt<'f>(
+    policy: Option<&DeadLetterPolicy>,
+    run: impl Fn() -> BoxFuture<'f, Result<()>>,
+) -> std::result::Result<(), (Error, u32)> {
+    let max_attempts = policy.map(|p| p.max_attempts.max(1)).unwrap_or(1);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match run().await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempts >= max_attempts => return Err((err, attempts)),
+            Err(_) => sleep(policy.map(|p| p.retry_backoff).unwrap_or_default()).await,
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use tokio::time::sleep;
+
+use super::{ConsumeResponse, KafkaService, Message, ProduceResponse, RecordHandler};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Client;
+
+pub const PRODUCER_ID_HEADER: &str = "producer-id";
+pub const PRODUCER_SEQUENCE_HEADER: &str = "producer-sequence";
+
+/// Stamps every message with the producer id and a sequence number, so the copies written by a
+/// retried produce can be dropped by a `DedupFilter` on the consumer side.
+pub struct IdempotentProducer {
+    client: Client,
+    producer_id: String,
+    sequence: AtomicU64,
+    pub max_retries: u32,
+    pub retry_backoff: Duration,
+}
+
+impl IdempotentProducer {
+    /// Uses a producer id unique to this process and start time.
+    pub fn new(client: Client) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Self::with_producer_id(client, format!("{:x}-{:x}", std::process::id(), nanos))
+    }
+
+    /// A stable id must not be shared by two producers running at the same time.
+    pub fn with_producer_id(client: Client, producer_id: impl Into<String>) -> Self {
+        Self {
+            client,
+            producer_id: producer_id.into(),
+            sequence: AtomicU64::new(0),
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(200),
+        }
+    }
+
+    pub fn producer_id(&self) -> &str {
+        &self.producer_id
+    }
+
+    pub fn stamp(&self, message: Message) -> Message {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        message
+            .with_header(PRODUCER_ID_HEADER, &self.producer_id)
+            .with_header(PRODUCER_SEQUENCE_HEADER, sequence.to_string())
+    }
+
+    /// Produces the messages, retrying the failed ones with the same stamps.
+    pub async fn produce(&self, messages: Vec<Message>) -> Result<Vec<ProduceResponse>> {
+        let mut pending: Vec<(usize, Message)> = messages
+            .into_iter()
+            .map(|message| self.stamp(message))
+            .enumerate()
+            .collect();
+        let mut responses: Vec<Option<ProduceResponse>> = vec![None; pending.len()];
+        let mut attempt = 0;
+        loop {
+            let batch = pending.iter().map(|(_, message)| message.clone()).collect();
+            let failed = match self.client.producer().produce_report(batch).await {
+                Ok(report) => {
+                    let mut failed = None;
+                    let mut retry = Vec::new();
+                    for ((index, message), produced) in pending.into_iter().zip(report.results) {
+                        match produced.result {
+                            Ok(response) => responses[index] = Some(response),
+                            Err(err) => {
+                                failed = Some(err);
+                                retry.push((index, message));
+                            }
+                        }
+                    }
+                    pending = retry;
+                    failed
+                }
+                Err(err) => Some(err),
+            };
+            match failed {
+                None => return Ok(responses.into_iter().flatten().collect()),
+                Some(err) if attempt >= self.max_retries => return Err(err),
+                Some(_) => {
+                    attempt += 1;
+                    sleep(self.retry_backoff).await;
+                }
+            }
+        }
+    }
+}
+
+/// Remembers the sequences seen per producer.
+#[async_trait]
+pub trait DedupStore: Send + Sync {
+    async fn contains(&self, producer_id: &str, sequence: u64) -> Result<bool>;
+    async fn insert(&self, producer_id: &str, sequence: u64) -> Result<()>;
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    sequences: HashSet<u64>,
+    order: VecDeque<u64>,
+    last_insert: u64,
+}
+
+#[derive(Debug, Default)]
+struct Windows {
+    windows: HashMap<String, Window>,
+    // producer ids by their last insert, the least recent is evicted first
+    by_insert: BTreeMap<u64, String>,
+    inserts: u64,
+}
+
+/// Keeps the last `window` sequences inserted for each of the last `max_producers` producers in memory.
+/// Sequences arrive out of order across partitions, so only evicted entries are forgotten
+/// and a sequence outside the window, or of an evicted producer, is reported as unseen.
+#[derive(Debug)]
+pub struct WindowStore {
+    window: usize,
+    max_producers: usize,
+    seen: Mutex<Windows>,
+}
+
+impl WindowStore {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            max_producers: 1000,
+            seen: Mutex::new(Windows::default()),
+        }
+    }
+
+    /// Every producer process has its own id, so the producers not inserted for longest are forgotten past this.
+    pub fn with_max_producers(mut self, max_producers: usize) -> Self {
+        self.max_producers = max_producers.max(1);
+        self
+    }
+}
+
+impl Default for WindowStore {
+    fn default() -> Self {
+        Self::new(10000)
+    }
+}
+
+#[async_trait]
+impl DedupStore for WindowStore {
+    async fn contains(&self, producer_id: &str, sequence: u64) -> Result<bool> {
+        let seen = self.seen.lock().unwrap();
+        Ok(seen
+            .windows
+            .get(producer_id)
+            .is_some_and(|window| window.sequences.contains(&sequence)))
+    }
+
+    async fn insert(&self, producer_id: &str, sequence: u64) -> Result<()> {
+        let mut seen = self.seen.lock().unwrap();
+        let seen = &mut *seen;
+        seen.inserts += 1;
+        let window = seen.windows.entry(producer_id.to_string()).or_default();
+        seen.by_insert.remove(&window.last_insert);
+        window.last_insert = seen.inserts;
+        seen.by_insert.insert(seen.inserts, producer_id.to_string());
+        if window.sequences.insert(sequence) {
+            window.order.push_back(sequence);
+            while window.order.len() > self.window {
+                if let Some(evicted) = window.order.pop_front() {
+                    window.sequences.remove(&evicted);
+                }
+            }
+        }
+        while seen.windows.len() > self.max_producers {
+            if let Some((_, evicted)) = seen.by_insert.pop_first() {
+                seen.windows.remove(&evicted);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn stamp(record: &ConsumeResponse) -> Result<Option<(&str, u64)>> {
+    let header = |key: &str| record.headers.iter().find(|h| h.key == key).map(|h| h.value.as_str());
+    match (header(PRODUCER_ID_HEADER), header(PRODUCER_SEQUENCE_HEADER)) {
+        (Some(producer_id), Some(sequence)) => {
+            let sequence = sequence.parse().map_err(|_| {
+                Error::new(
+                    format!("invalid producer sequence {}", sequence),
+                    ErrorKind::InvalidData,
+                )
+            })?;
+            Ok(Some((producer_id, sequence)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Drops records already seen from the same producer; records without a stamp always pass.
+pub struct DedupFilter<S = WindowStore> {
+    store: S,
+}
+
+impl<S: DedupStore> DedupFilter<S> {
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    pub async fn is_duplicate(&self, record: &ConsumeResponse) -> Result<bool> {
+        match stamp(record)? {
+            Some((producer_id, sequence)) => self.store.contains(producer_id, sequence).await,
+            None => Ok(false),
+        }
+    }
+
+    pub async fn mark_seen(&self, record: &ConsumeResponse) -> Result<()> {
+        match stamp(record)? {
+            Some((producer_id, sequence)) => self.store.insert(producer_id, sequence).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps the first copy of every record and marks it as seen.
+    pub async fn filter(&self, records: Vec<ConsumeResponse>) -> Result<Vec<ConsumeResponse>> {
+        let mut unique = Vec::with_capacity(records.len());
+        for record in records {
+            if !self.is_duplicate(&record).await? {
+                self.mark_seen(&record).await?;
+                unique.push(record);
+            }
+        }
+        Ok(unique)
+    }
+
+    /// Skips duplicates before calling the handler, marking a record as seen only once it was handled.
+    pub fn handler<H: RecordHandler>(self, handler: H) -> Deduplicated<H, S> {
+        Deduplicated { handler, filter: self }
+    }
+}
+
+impl Default for DedupFilter {
+    fn default() -> Self {
+        Self::new(WindowStore::default())
+    }
+}
+
+pub struct Deduplicated<H, S> {
+    handler: H,
+    filter: DedupFilter<S>,
+}
+
+#[async_trait]
+impl<H: RecordHandler, S: DedupStore> RecordHandler for Deduplicated<H, S> {
+    async fn handle(&self, record: &ConsumeResponse) -> Result<()> {
+        if self.filter.is_duplicate(record).await? {
+            return Ok(());
+        }
+        self.handler.handle(record).await?;
+        self.filter.mark_seen(record).await
+    }
+}
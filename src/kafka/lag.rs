@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use super::stream::wait_or_stop;
+use super::{
+    CommittedOffset, ConsumerInstance, GroupInstance, KafkaService, OffsetSpec, PartitionOffset, StopHandle,
+    TopicPartition, TopicResponse,
+};
+use crate::error::Result;
+use crate::{Client, Handler};
+
+/// Partition count per topic, the committed offsets are queried for every partition of the subscribed topics.
+pub type PartitionCounts = BTreeMap<String, u32>;
+
+/// Partition counts of the topics returned by the admin `list_topics`.
+pub fn partition_counts(topics: &[TopicResponse]) -> PartitionCounts {
+    topics.iter().map(|t| (t.topic_name.clone(), t.partitions)).collect()
+}
+
+impl ConsumerInstance {
+    /// Every partition of the subscribed topics found in `counts`, and the subscribed topics missing from it.
+    pub fn partitions(&self, counts: &PartitionCounts) -> (Vec<TopicPartition>, Vec<String>) {
+        let mut partitions = Vec::new();
+        let mut unknown = Vec::new();
+        for topic in &self.topics {
+            match counts.get(&topic.topic) {
+                Some(count) => {
+                    partitions.extend((0..*count as u64).map(|partition| TopicPartition::new(&topic.topic, partition)))
+                }
+                None => unknown.push(topic.topic.clone()),
+            }
+        }
+        (partitions, unknown)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: u64,
+    pub committed: Option<u64>,
+    pub end: u64,
+    pub lag: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupLag {
+    pub group: String,
+    pub partitions: Vec<PartitionLag>,
+    /// Subscribed topics left out because their partition count was not given.
+    pub unknown_topics: Vec<String>,
+}
+
+impl GroupLag {
+    /// Covers the partitions with an end offset or a commit,
+    /// those without a committed offset lag from the earliest offset still in the log.
+    pub fn new(
+        group: impl Into<String>,
+        committed: &[CommittedOffset],
+        earliest: &[PartitionOffset],
+        latest: &[PartitionOffset],
+    ) -> Self {
+        let offsets = |offsets: &[PartitionOffset]| -> BTreeMap<(String, u64), u64> {
+            offsets
+                .iter()
+                .map(|o| {
+                    (
+                        (o.topic.clone(), o.partition),
+                        u64::try_from(o.offset).unwrap_or_default(),
+                    )
+                })
+                .collect()
+        };
+        let (earliest, latest) = (offsets(earliest), offsets(latest));
+        let mut partitions: BTreeMap<(String, u64), Option<u64>> =
+            latest.keys().map(|key| (key.clone(), None)).collect();
+        for offset in committed {
+            let entry = partitions.entry((offset.topic.clone(), offset.partition)).or_default();
+            *entry = (*entry).max(offset.committed());
+        }
+        let partitions = partitions
+            .into_iter()
+            .map(|((topic, partition), committed)| {
+                let key = (topic, partition);
+                let end = latest.get(&key).copied().unwrap_or_default();
+                let start = committed.or_else(|| earliest.get(&key).copied()).unwrap_or_default();
+                PartitionLag {
+                    topic: key.0,
+                    partition,
+                    committed,
+                    end,
+                    lag: end.saturating_sub(start),
+                }
+            })
+            .collect();
+        Self {
+            group: group.into(),
+            partitions,
+            unknown_topics: Vec::new(),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.partitions.iter().map(|p| p.lag).sum()
+    }
+
+    pub fn by_topic(&self) -> BTreeMap<&str, u64> {
+        let mut topics = BTreeMap::new();
+        for partition in &self.partitions {
+            *topics.entry(partition.topic.as_str()).or_default() += partition.lag;
+        }
+        topics
+    }
+}
+
+impl Handler<'_> {
+    /// Lag of every partition of the topics the group's consumer instances are subscribed to.
+    /// Subscribed topics missing from `counts` are skipped and listed in `GroupLag::unknown_topics`.
+    pub async fn group_lag(&self, group: &str, counts: &PartitionCounts) -> Result<GroupLag> {
+        let groups = self.list_consumers().await?;
+        self.lag_of(group, &groups, counts).await
+    }
+
+    pub async fn lag(&self, counts: &PartitionCounts) -> Result<Vec<GroupLag>> {
+        let groups = self.list_consumers().await?;
+        let mut lags = Vec::with_capacity(groups.len());
+        for group in &groups {
+            lags.push(self.lag_of(&group.name, &groups, counts).await?);
+        }
+        Ok(lags)
+    }
+
+    async fn lag_of(&self, group: &str, groups: &[GroupInstance], counts: &PartitionCounts) -> Result<GroupLag> {
+        let mut committed = Vec::new();
+        let mut partitions: Vec<TopicPartition> = Vec::new();
+        let mut unknown_topics: Vec<String> = Vec::new();
+        for instance in groups.iter().filter(|g| g.name == group).flat_map(|g| &g.instances) {
+            let (subscribed, unknown) = instance.partitions(counts);
+            for topic in unknown {
+                if !unknown_topics.contains(&topic) {
+                    unknown_topics.push(topic);
+                }
+            }
+            if subscribed.is_empty() {
+                continue;
+            }
+            committed.extend(self.committed(group, &instance.name, subscribed.clone()).await?);
+            for partition in subscribed {
+                if !partitions.contains(&partition) {
+                    partitions.push(partition);
+                }
+            }
+        }
+        let mut lag = if partitions.is_empty() {
+            GroupLag::new(group, &[], &[], &[])
+        } else {
+            let earliest = self.partition_offsets(OffsetSpec::Earliest, partitions.clone()).await?;
+            let latest = self.partition_offsets(OffsetSpec::Latest, partitions).await?;
+            GroupLag::new(group, &committed, &earliest, &latest)
+        };
+        lag.unknown_topics = unknown_topics;
+        Ok(lag)
+    }
+}
+
+/// Fires when the lag of a partition goes above `max_lag`, and again once it is back at or below it.
+#[derive(Debug, Clone)]
+pub struct LagThreshold {
+    pub group: Option<String>,
+    pub topic: Option<String>,
+    pub max_lag: u64,
+}
+
+impl LagThreshold {
+    pub fn new(max_lag: u64) -> Self {
+        Self {
+            group: None,
+            topic: None,
+            max_lag,
+        }
+    }
+
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    fn matches(&self, group: &str, partition: &PartitionLag) -> bool {
+        self.group.as_deref().is_none_or(|g| g == group) && self.topic.as_deref().is_none_or(|t| t == partition.topic)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LagAlert {
+    pub group: String,
+    pub partition: PartitionLag,
+    pub max_lag: u64,
+    pub exceeded: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LagMonitorConfig {
+    pub interval: Duration,
+    /// Groups to watch, all groups when empty.
+    pub groups: Vec<String>,
+    /// Partition counts of the watched topics, other topics are reported in `GroupLag::unknown_topics`.
+    pub partitions: PartitionCounts,
+    pub max_retries: u32,
+}
+
+impl Default for LagMonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            groups: Vec::new(),
+            partitions: PartitionCounts::new(),
+            max_retries: 5,
+        }
+    }
+}
+
+type Reporter = Box<dyn Fn(&[GroupLag]) + Send + Sync>;
+type Alerter = Box<dyn Fn(&LagAlert) + Send + Sync>;
+
+pub struct LagMonitor {
+    client: Client,
+    config: LagMonitorConfig,
+    reporters: Vec<Reporter>,
+    thresholds: Vec<(LagThreshold, Alerter)>,
+    stop: StopHandle,
+    stopped: watch::Receiver<bool>,
+}
+
+impl LagMonitor {
+    pub fn new(client: Client, config: LagMonitorConfig) -> Self {
+        let (stop, stopped) = StopHandle::new();
+        Self {
+            client,
+            config,
+            reporters: Vec::new(),
+            thresholds: Vec::new(),
+            stop,
+            stopped,
+        }
+    }
+
+    pub fn on_report(mut self, reporter: impl Fn(&[GroupLag]) + Send + Sync + 'static) -> Self {
+        self.reporters.push(Box::new(reporter));
+        self
+    }
+
+    pub fn on_threshold(
+        mut self,
+        threshold: LagThreshold,
+        alerter: impl Fn(&LagAlert) + Send + Sync + 'static,
+    ) -> Self {
+        self.thresholds.push((threshold, Box::new(alerter)));
+        self
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Reports the lag every interval until stopped, failing after `max_retries` consecutive errors.
+    pub async fn run(&self) -> Result<()> {
+        let mut stopped = self.stopped.clone();
+        let mut exceeded = HashSet::new();
+        let mut failures = 0;
+        while !*stopped.borrow() {
+            match self.poll().await {
+                Ok(lags) => {
+                    failures = 0;
+                    self.reporters.iter().for_each(|report| report(&lags));
+                    self.alert(&lags, &mut exceeded);
+                }
+                Err(err) => {
+                    failures += 1;
+                    if failures > self.config.max_retries {
+                        return Err(err);
+                    }
+                }
+            }
+            wait_or_stop(&mut stopped, self.config.interval).await;
+        }
+        Ok(())
+    }
+
+    async fn poll(&self) -> Result<Vec<GroupLag>> {
+        let handler = self.client.handler("");
+        if self.config.groups.is_empty() {
+            return handler.lag(&self.config.partitions).await;
+        }
+        let groups = handler.list_consumers().await?;
+        let mut lags = Vec::with_capacity(self.config.groups.len());
+        for group in &self.config.groups {
+            lags.push(handler.lag_of(group, &groups, &self.config.partitions).await?);
+        }
+        Ok(lags)
+    }
+
+    fn alert(&self, lags: &[GroupLag], exceeded: &mut HashSet<(usize, String, String, u64)>) {
+        for (index, (threshold, alerter)) in self.thresholds.iter().enumerate() {
+            for lag in lags {
+                for partition in lag.partitions.iter().filter(|p| threshold.matches(&lag.group, p)) {
+                    let key = (index, lag.group.clone(), partition.topic.clone(), partition.partition);
+                    let above = partition.lag > threshold.max_lag;
+                    let changed = if above {
+                        exceeded.insert(key)
+                    } else {
+                        exceeded.remove(&key)
+                    };
+                    if changed {
+                        alerter(&LagAlert {
+                            group: lag.group.clone(),
+                            partition: partition.clone(),
+                            max_lag: threshold.max_lag,
+                            exceeded: above,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{ClusterResponse, KafkaService, TopicResponse};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Handler;
+
+/// Picks the only match, failing with the ids of all matches when the name is ambiguous.
+pub(crate) fn unique<T>(
+    what: &str,
+    name: &str,
+    found: impl IntoIterator<Item = T>,
+    id: impl Fn(&T) -> &str,
+) -> Result<Option<T>> {
+    let mut found: Vec<T> = found.into_iter().collect();
+    if found.len() > 1 {
+        let ids: Vec<&str> = found.iter().map(id).collect();
+        return Err(Error::new(
+            format!(
+                "more than one {} is named {}, use one of the ids {}",
+                what,
+                name,
+                ids.join(", ")
+            ),
+            ErrorKind::InvalidData,
+        ));
+    }
+    Ok(found.pop())
+}
+
+#[derive(Debug)]
+struct Cached<T> {
+    at: Instant,
+    items: Vec<T>,
+}
+
+/// Keeps the cluster and topic lists used by the name lookups for `ttl`.
+/// A name missing from a cached list is looked up again, so new resources are found right away.
+#[derive(Debug)]
+pub struct NameCache {
+    ttl: Duration,
+    clusters: Mutex<Option<Cached<ClusterResponse>>>,
+    topics: Mutex<HashMap<String, Cached<TopicResponse>>>,
+}
+
+impl NameCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            clusters: Mutex::new(None),
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn clear(&self) {
+        *self.clusters.lock().unwrap() = None;
+        self.topics.lock().unwrap().clear();
+    }
+
+    fn fresh<T: Clone>(&self, cached: Option<&Cached<T>>) -> Option<Vec<T>> {
+        cached
+            .filter(|cached| cached.at.elapsed() < self.ttl)
+            .map(|cached| cached.items.clone())
+    }
+
+    fn clusters(&self) -> Option<Vec<ClusterResponse>> {
+        self.fresh(self.clusters.lock().unwrap().as_ref())
+    }
+
+    fn set_clusters(&self, items: Vec<ClusterResponse>) {
+        *self.clusters.lock().unwrap() = Some(Cached {
+            at: Instant::now(),
+            items,
+        });
+    }
+
+    fn topics(&self, cluster_id: &str) -> Option<Vec<TopicResponse>> {
+        self.fresh(self.topics.lock().unwrap().get(cluster_id))
+    }
+
+    fn set_topics(&self, cluster_id: &str, items: Vec<TopicResponse>) {
+        self.topics.lock().unwrap().insert(
+            cluster_id.to_string(),
+            Cached {
+                at: Instant::now(),
+                items,
+            },
+        );
+    }
+}
+
+fn cluster_named(clusters: &[ClusterResponse], name: &str) -> Result<Option<ClusterResponse>> {
+    let found = clusters.iter().filter(|c| c.name == name).cloned();
+    unique("cluster", name, found, |c| c.cluster_id.as_str())
+}
+
+fn topic_named(topics: &[TopicResponse], name: &str) -> Result<Option<TopicResponse>> {
+    let found = topics.iter().filter(|t| t.topic_name == name).cloned();
+    unique("topic", name, found, |t| t.topic_id.as_str())
+}
+
+impl Handler<'_> {
+    /// Uses the client's name cache when it has one.
+    pub async fn find_cluster_by_name(&self, name: &str) -> Result<Option<ClusterResponse>> {
+        let cache = self.client.name_cache.as_deref();
+        if let Some(clusters) = cache.and_then(|cache| cache.clusters()) {
+            if let Some(cluster) = cluster_named(&clusters, name)? {
+                return Ok(Some(cluster));
+            }
+        }
+        let clusters = self.list_clusters().await?;
+        let found = cluster_named(&clusters, name)?;
+        if let Some(cache) = cache {
+            cache.set_clusters(clusters);
+        }
+        Ok(found)
+    }
+
+    pub async fn find_topic_by_name(&self, cluster_id: &str, name: &str) -> Result<Option<TopicResponse>> {
+        let cache = self.client.name_cache.as_deref();
+        if let Some(topics) = cache.and_then(|cache| cache.topics(cluster_id)) {
+            if let Some(topic) = topic_named(&topics, name)? {
+                return Ok(Some(topic));
+            }
+        }
+        let topics = self.list_topics(cluster_id).await?;
+        let found = topic_named(&topics, name)?;
+        if let Some(cache) = cache {
+            cache.set_topics(cluster_id, topics);
+        }
+        Ok(found)
+    }
+}
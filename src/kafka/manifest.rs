@@ -0,0 +1,492 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::admin::{check_cluster, immutable, topic_diff};
+use super::lookup::unique;
+use super::{
+    ClusterResponse, CreateClusterRequest, CreateCredentialRequest, CreateTopicCleanupPolicy, CreateTopicRequest,
+    CredentialPermissions, CredentialResponse, CredentialState, KafkaService, ReconfigureTopicRequest, Region,
+    TopicResponse, WaitConfig,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Handler;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Clusters missing from the manifest are pruned only when their name starts with this prefix,
+    /// without it pruning never deletes a cluster.
+    #[serde(default)]
+    pub cluster_prefix: Option<String>,
+    #[serde(default)]
+    pub clusters: Vec<ClusterSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClusterSpec {
+    pub name: String,
+    pub region: Region,
+    #[serde(default)]
+    pub multizone: bool,
+    #[serde(default)]
+    pub topics: Vec<TopicSpec>,
+    #[serde(default)]
+    pub credentials: Vec<CredentialSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopicSpec {
+    pub name: String,
+    pub partitions: u32,
+    pub retention_time: i32,
+    pub retention_size: i32,
+    pub max_message_size: i32,
+    pub cleanup_policy: CreateTopicCleanupPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CredentialSpec {
+    pub name: String,
+    pub topic: String,
+    pub permissions: CredentialPermissions,
+}
+
+impl ClusterSpec {
+    fn request(&self) -> CreateClusterRequest {
+        CreateClusterRequest {
+            name: self.name.clone(),
+            region: self.region.clone(),
+            multizone: self.multizone,
+        }
+    }
+}
+
+impl TopicSpec {
+    fn request(&self, cluster_id: String) -> CreateTopicRequest {
+        CreateTopicRequest {
+            name: self.name.clone(),
+            partitions: self.partitions,
+            retention_time: self.retention_time,
+            retention_size: self.retention_size,
+            max_message_size: self.max_message_size,
+            cleanup_policy: self.cleanup_policy.clone(),
+            cluster_id,
+        }
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::new(msg, ErrorKind::InvalidData)
+}
+
+fn duplicates<'a>(prefix: &str, names: impl Iterator<Item = &'a String>) -> Result<()> {
+    let mut seen = BTreeSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(invalid(format!("{}{} is declared more than once", prefix, name)));
+        }
+    }
+    Ok(())
+}
+
+impl Manifest {
+    pub fn from_yaml(manifest: &str) -> Result<Self> {
+        let manifest: Self =
+            serde_yaml::from_str(manifest).map_err(|err| invalid(format!("invalid yaml manifest: {}", err)))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    pub fn from_toml(manifest: &str) -> Result<Self> {
+        let manifest: Self =
+            toml::from_str(manifest).map_err(|err| invalid(format!("invalid toml manifest: {}", err)))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Reads a `.toml` file as toml and anything else as yaml.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let manifest = std::fs::read_to_string(path)
+            .map_err(|err| Error::new(format!("cannot read {}: {}", path.display(), err), ErrorKind::Internal))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&manifest),
+            _ => Self::from_yaml(&manifest),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        duplicates("cluster ", self.clusters.iter().map(|c| &c.name))?;
+        for cluster in &self.clusters {
+            duplicates(
+                &format!("topic {}/", cluster.name),
+                cluster.topics.iter().map(|t| &t.name),
+            )?;
+            duplicates(
+                &format!("credential {}/", cluster.name),
+                cluster.credentials.iter().map(|c| &c.name),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Clusters, topics and active credentials of the account.
+#[derive(Debug, Clone, Default)]
+pub struct LiveState {
+    pub clusters: Vec<ClusterResponse>,
+    /// Topics per cluster id.
+    pub topics: BTreeMap<String, Vec<TopicResponse>>,
+    pub credentials: Vec<CredentialResponse>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    CreateCluster(CreateClusterRequest),
+    CreateTopic {
+        cluster: String,
+        spec: TopicSpec,
+    },
+    ReconfigureTopic {
+        cluster: String,
+        topic: String,
+        topic_id: String,
+        req: ReconfigureTopicRequest,
+        /// Setting names with their current and wanted values.
+        diff: Vec<(&'static str, i32, i32)>,
+    },
+    CreateCredential {
+        cluster: String,
+        spec: CredentialSpec,
+    },
+    DeleteCredential {
+        cluster: String,
+        credential: String,
+        credential_id: String,
+    },
+    DeleteTopic {
+        cluster: String,
+        topic: String,
+        topic_id: String,
+    },
+    DeleteCluster {
+        cluster: String,
+        cluster_id: String,
+    },
+}
+
+impl Change {
+    pub fn is_delete(&self) -> bool {
+        matches!(
+            self,
+            Change::DeleteCredential { .. } | Change::DeleteTopic { .. } | Change::DeleteCluster { .. }
+        )
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::CreateCluster(req) => {
+                let multizone = if req.multizone { ", multizone" } else { "" };
+                write!(f, "+ cluster {} ({}{})", req.name, req.region, multizone)
+            }
+            Change::CreateTopic { cluster, spec } => write!(
+                f,
+                "+ topic {}/{} (partitions {}, retention time {}, retention size {}, max message size {}, {})",
+                cluster,
+                spec.name,
+                spec.partitions,
+                spec.retention_time,
+                spec.retention_size,
+                spec.max_message_size,
+                spec.cleanup_policy.as_str()
+            ),
+            Change::ReconfigureTopic {
+                cluster, topic, diff, ..
+            } => {
+                write!(f, "~ topic {}/{}", cluster, topic)?;
+                for (i, (setting, current, wanted)) in diff.iter().enumerate() {
+                    let separator = if i == 0 { ": " } else { ", " };
+                    write!(f, "{}{} {} -> {}", separator, setting, current, wanted)?;
+                }
+                Ok(())
+            }
+            Change::CreateCredential { cluster, spec } => write!(
+                f,
+                "+ credential {}/{} ({} on {})",
+                cluster,
+                spec.name,
+                spec.permissions.as_str(),
+                spec.topic
+            ),
+            Change::DeleteCredential {
+                cluster, credential, ..
+            } => write!(f, "- credential {}/{}", cluster, credential),
+            Change::DeleteTopic { cluster, topic, .. } => write!(f, "- topic {}/{}", cluster, topic),
+            Change::DeleteCluster { cluster, .. } => write!(f, "- cluster {}", cluster),
+        }
+    }
+}
+
+/// Changes bringing the live state to the manifest, in the order they are applied:
+/// creates and updates first, then deletes of credentials, topics and clusters.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    /// Ids of the clusters which already exist, by name.
+    cluster_ids: BTreeMap<String, String>,
+}
+
+impl Plan {
+    /// Diffs the manifest against the live state. Resources missing from the manifest are deleted only when
+    /// `prune` is set: topics and credentials within the clusters it declares, and clusters named with its
+    /// `cluster_prefix`.
+    /// Fails when a resource differs in a setting that cannot be changed in place.
+    pub fn new(manifest: &Manifest, live: &LiveState, prune: bool) -> Result<Self> {
+        manifest.validate()?;
+        let mut plan = Plan::default();
+        let mut delete_credentials = Vec::new();
+        let mut delete_topics = Vec::new();
+        let no_topics = Vec::new();
+
+        for spec in &manifest.clusters {
+            let found = live.clusters.iter().filter(|c| c.name == spec.name);
+            let cluster = match unique("cluster", &spec.name, found, |c| c.cluster_id.as_str())? {
+                Some(cluster) => cluster,
+                None => {
+                    plan.changes.push(Change::CreateCluster(spec.request()));
+                    for topic in &spec.topics {
+                        plan.changes.push(Change::CreateTopic {
+                            cluster: spec.name.clone(),
+                            spec: topic.clone(),
+                        });
+                    }
+                    for credential in &spec.credentials {
+                        plan.changes.push(Change::CreateCredential {
+                            cluster: spec.name.clone(),
+                            spec: credential.clone(),
+                        });
+                    }
+                    continue;
+                }
+            };
+            check_cluster(&format!("cluster {}", spec.name), cluster, &spec.request())?;
+            plan.cluster_ids.insert(spec.name.clone(), cluster.cluster_id.clone());
+
+            let topics = live.topics.get(&cluster.cluster_id).unwrap_or(&no_topics);
+            for topic_spec in &spec.topics {
+                let found = topics.iter().filter(|t| t.topic_name == topic_spec.name);
+                match unique("topic", &topic_spec.name, found, |t| t.topic_id.as_str())? {
+                    Some(topic) => {
+                        if let Some(change) = Self::topic_change(&spec.name, topic, topic_spec)? {
+                            plan.changes.push(change);
+                        }
+                    }
+                    None => plan.changes.push(Change::CreateTopic {
+                        cluster: spec.name.clone(),
+                        spec: topic_spec.clone(),
+                    }),
+                }
+            }
+            if prune {
+                for topic in topics {
+                    if !spec.topics.iter().any(|t| t.name == topic.topic_name) {
+                        delete_topics.push(Change::DeleteTopic {
+                            cluster: spec.name.clone(),
+                            topic: topic.topic_name.clone(),
+                            topic_id: topic.topic_id.clone(),
+                        });
+                    }
+                }
+            }
+
+            let credentials: Vec<&CredentialResponse> = live
+                .credentials
+                .iter()
+                .filter(|c| c.cluster_id == cluster.cluster_id && matches!(c.state, CredentialState::Active))
+                .collect();
+            for credential_spec in &spec.credentials {
+                let found = credentials
+                    .iter()
+                    .copied()
+                    .filter(|c| c.credential_name == credential_spec.name);
+                match unique("credential", &credential_spec.name, found, |c| c.credential_id.as_str())? {
+                    Some(credential) => {
+                        let mut fields = Vec::new();
+                        if credential.topic != credential_spec.topic {
+                            fields.push("topic");
+                        }
+                        if credential.permissions != credential_spec.permissions {
+                            fields.push("permissions");
+                        }
+                        immutable(&format!("credential {}/{}", spec.name, credential_spec.name), fields)?;
+                    }
+                    None => plan.changes.push(Change::CreateCredential {
+                        cluster: spec.name.clone(),
+                        spec: credential_spec.clone(),
+                    }),
+                }
+            }
+            if prune {
+                for credential in &credentials {
+                    if !spec.credentials.iter().any(|c| c.name == credential.credential_name) {
+                        delete_credentials.push(Change::DeleteCredential {
+                            cluster: spec.name.clone(),
+                            credential: credential.credential_name.clone(),
+                            credential_id: credential.credential_id.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        plan.changes.extend(delete_credentials);
+        plan.changes.extend(delete_topics);
+        if let Some(prefix) = manifest.cluster_prefix.as_deref().filter(|_| prune) {
+            for cluster in &live.clusters {
+                if cluster.name.starts_with(prefix) && !manifest.clusters.iter().any(|c| c.name == cluster.name) {
+                    plan.changes.push(Change::DeleteCluster {
+                        cluster: cluster.name.clone(),
+                        cluster_id: cluster.cluster_id.clone(),
+                    });
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    fn topic_change(cluster: &str, topic: &TopicResponse, spec: &TopicSpec) -> Result<Option<Change>> {
+        let req = spec.request(topic.cluster_id.clone());
+        let diff = topic_diff(&format!("topic {}/{}", cluster, spec.name), topic, &req)?;
+        Ok(diff.map(|diff| Change::ReconfigureTopic {
+            cluster: cluster.to_string(),
+            topic: spec.name.clone(),
+            topic_id: topic.topic_id.clone(),
+            req: diff.req,
+            diff: diff.settings,
+        }))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn deletes(&self) -> usize {
+        self.changes.iter().filter(|c| c.is_delete()).count()
+    }
+}
+
+/// The dry-run diff, one change per line followed by a summary.
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        let updates = self
+            .changes
+            .iter()
+            .filter(|c| matches!(c, Change::ReconfigureTopic { .. }))
+            .count();
+        let deletes = self.deletes();
+        writeln!(
+            f,
+            "Plan: {} to create, {} to update, {} to delete.",
+            self.changes.len() - updates - deletes,
+            updates,
+            deletes
+        )
+    }
+}
+
+impl Handler<'_> {
+    pub async fn live_state(&self) -> Result<LiveState> {
+        let clusters = self.list_clusters().await?;
+        let mut topics = BTreeMap::new();
+        for cluster in &clusters {
+            topics.insert(cluster.cluster_id.clone(), self.list_topics(&cluster.cluster_id).await?);
+        }
+        let credentials = self.list_credentials().await?;
+        Ok(LiveState {
+            clusters,
+            topics,
+            credentials,
+        })
+    }
+
+    pub async fn plan_manifest(&self, manifest: &Manifest, prune: bool) -> Result<Plan> {
+        Plan::new(manifest, &self.live_state().await?, prune)
+    }
+
+    /// Applies the changes in order, waiting for new clusters to become active before creating their topics.
+    /// Refuses a plan with deletes unless `allow_delete` is set. Stops at the first failing change;
+    /// planning again afterwards picks up the remaining ones.
+    pub async fn apply_plan(&self, plan: &Plan, allow_delete: bool) -> Result<()> {
+        let deletes = plan.deletes();
+        if deletes > 0 && !allow_delete {
+            return Err(invalid(format!(
+                "the plan deletes {} resources, apply it with deletes allowed to proceed",
+                deletes
+            )));
+        }
+        let mut cluster_ids = plan.cluster_ids.clone();
+        for (applied, change) in plan.changes.iter().enumerate() {
+            self.apply_change(change, &mut cluster_ids)
+                .await
+                .map_err(|err| err.prefixed(format!("{} failed after {} changes", change, applied)))?;
+        }
+        Ok(())
+    }
+
+    async fn apply_change(&self, change: &Change, cluster_ids: &mut BTreeMap<String, String>) -> Result<()> {
+        let cluster_id = |cluster: &str| {
+            cluster_ids
+                .get(cluster)
+                .cloned()
+                .ok_or_else(|| Error::new(format!("unknown cluster {}", cluster), ErrorKind::Internal))
+        };
+        match change {
+            Change::CreateCluster(req) => {
+                let cluster = self.create_cluster(req.clone()).await?;
+                let cluster = self
+                    .wait_until_cluster_active(&cluster.cluster_id, WaitConfig::default())
+                    .await?;
+                cluster_ids.insert(req.name.clone(), cluster.cluster_id);
+            }
+            Change::CreateTopic { cluster, spec } => {
+                self.create_topic(spec.request(cluster_id(cluster)?)).await?;
+            }
+            Change::ReconfigureTopic { topic_id, req, .. } => {
+                self.reconfigure_topic(req.clone(), topic_id).await?;
+            }
+            Change::CreateCredential { cluster, spec } => {
+                let req = CreateCredentialRequest {
+                    credential_name: spec.name.clone(),
+                    topic: spec.topic.clone(),
+                    permissions: spec.permissions.clone(),
+                    cluster_id: cluster_id(cluster)?,
+                };
+                self.create_credential(req).await?;
+            }
+            Change::DeleteCredential { credential_id, .. } => {
+                self.delete_credential(credential_id).await?;
+            }
+            Change::DeleteTopic { topic_id, .. } => {
+                self.delete_topic(topic_id).await?;
+            }
+            Change::DeleteCluster { cluster_id, .. } => {
+                self.delete_cluster(cluster_id).await?;
+            }
+        }
+        Ok(())
+    }
+}
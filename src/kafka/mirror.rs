@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+
+use super::dead_letter::produce_all;
+use super::{
+    BatchHandler, ConsumeRequest, ConsumeResponse, ConsumerRunner, GroupLag, Message, PartitionCounts, RunnerConfig,
+    StopHandle,
+};
+use crate::error::Result;
+use crate::Client;
+
+#[derive(Debug, Clone, Default)]
+pub struct MirrorConfig {
+    pub runner: RunnerConfig,
+    /// Target topic per source topic, records of unmapped topics keep their topic name.
+    pub topics: BTreeMap<String, String>,
+}
+
+/// Replicates records consumed from the source cluster to the target cluster, keeping keys, headers and partitions.
+/// Source offsets are committed only after the records were produced to the target, so progress is
+/// checkpointed in the source consumer group and a restarted mirror resumes where it stopped.
+pub struct Mirror {
+    source: Client,
+    group: String,
+    runner: ConsumerRunner,
+    producer: Producer,
+}
+
+struct Producer {
+    target: Client,
+    topics: BTreeMap<String, String>,
+    mirrored: AtomicU64,
+}
+
+impl Mirror {
+    pub fn new(
+        source: Client,
+        target: Client,
+        group: impl Into<String>,
+        consumer: impl Into<String>,
+        req: ConsumeRequest,
+        config: MirrorConfig,
+    ) -> Self {
+        let group = group.into();
+        let runner = ConsumerRunner::new(source.clone(), &group, consumer, req, config.runner);
+        Self {
+            source,
+            group,
+            runner,
+            producer: Producer {
+                target,
+                topics: config.topics,
+                mirrored: AtomicU64::new(0),
+            },
+        }
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.runner.stop_handle()
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        self.runner.run_batch(&self.producer).await
+    }
+
+    pub fn mirrored(&self) -> u64 {
+        self.producer.mirrored.load(Ordering::Relaxed)
+    }
+
+    /// Replication lag: records in the source not yet mirrored and committed.
+    pub async fn lag(&self, counts: &PartitionCounts) -> Result<GroupLag> {
+        self.source.handler("").group_lag(&self.group, counts).await
+    }
+}
+
+#[async_trait]
+impl BatchHandler for &Producer {
+    async fn handle_batch(&self, records: &[ConsumeResponse]) -> Result<()> {
+        let messages: Vec<Message> = records
+            .iter()
+            .map(|record| {
+                let topic = self.topics.get(&record.topic).unwrap_or(&record.topic);
+                let mut message = Message::new(topic, &record.value, Some(record.partition), Some(&record.key));
+                message.headers = record.headers.clone();
+                message
+            })
+            .collect();
+        produce_all(&self.target, messages, "mirror target").await?;
+        self.mirrored.fetch_add(records.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
@@ -1,18 +1,140 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use reqwest::header::{HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 
-use crate::error::Result;
+use crate::error::{Error, ErrorKind, Result};
 use crate::Handler;
 
-#[derive(Debug, Clone, Serialize)]
-pub enum CreateClusterRegion {
-    #[serde(rename = "us-east-1")]
-    UsEast1,
-    #[serde(rename = "eu-west-1")]
-    EuWest1,
+/// Longest path and query sent by the GET forms of produce and fetch, many proxies reject longer ones.
+pub const MAX_GET_URL_LENGTH: usize = 8192;
+
+mod admin;
+pub use admin::*;
+mod archive;
+pub use archive::*;
+mod batch;
+pub use batch::*;
+mod dead_letter;
+pub use dead_letter::*;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "compression")]
+pub use compression::*;
+#[cfg(feature = "schema")]
+mod schema;
+#[cfg(feature = "schema")]
+pub use schema::*;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+#[cfg(feature = "webhook")]
+mod webhook;
+#[cfg(feature = "webhook")]
+pub use webhook::*;
+mod idempotent;
+pub use idempotent::*;
+mod lag;
+pub use lag::*;
+mod lookup;
+pub use lookup::*;
+#[cfg(feature = "manifest")]
+mod manifest;
+#[cfg(feature = "manifest")]
+pub use manifest::*;
+mod mirror;
+pub use mirror::*;
+mod outbox;
+pub use outbox::*;
+mod reset;
+pub use reset::*;
+mod retry;
+pub use retry::*;
+mod runner;
+pub use runner::*;
+mod stream;
+pub use stream::*;
+
+// String valued enums which keep values the server added later in `Unknown`.
+macro_rules! string_enum {
+    ($name:ident { $($variant:ident => $value:literal,)* }) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+            Unknown(String),
+        }
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $value,)*
+                    $name::Unknown(value) => value,
+                }
+            }
+        }
+        impl From<&str> for $name {
+            fn from(value: &str) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    _ => $name::Unknown(value.to_string()),
+                }
+            }
+        }
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+        impl Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                let value = Cow::<str>::deserialize(deserializer)?;
+                Ok($name::from(value.as_ref()))
+            }
+        }
+    };
 }
 
+string_enum!(Region {
+    UsEast1 => "us-east-1",
+    UsWest1 => "us-west-1",
+    UsWest2 => "us-west-2",
+    EuWest1 => "eu-west-1",
+    EuCentral1 => "eu-central-1",
+    ApSoutheast1 => "ap-southeast-1",
+    ApSoutheast2 => "ap-southeast-2",
+    ApNortheast1 => "ap-northeast-1",
+    ApSouth1 => "ap-south-1",
+    SaEast1 => "sa-east-1",
+});
+
+pub type CreateClusterRegion = Region;
+
+string_enum!(ClusterType {
+    Free => "free",
+    PayAsYouGo => "payasyougo",
+    Fixed => "fixed",
+    Pro => "pro",
+    Enterprise => "enterprise",
+});
+
+string_enum!(ResourceState {
+    Creating => "creating",
+    Pending => "pending",
+    Active => "active",
+    Updating => "updating",
+    Deleting => "deleting",
+    Deleted => "deleted",
+    Failed => "failed",
+});
+
 #[derive(Debug, Clone, Serialize)]
 pub struct CreateClusterRequest {
     pub name: String,
@@ -25,12 +147,20 @@ pub struct RenameClusterRequest {
     pub name: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum CreateTopicCleanupPolicy {
     Compact,
     Delete,
 }
+impl CreateTopicCleanupPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CreateTopicCleanupPolicy::Compact => "compact",
+            CreateTopicCleanupPolicy::Delete => "delete",
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize)]
 pub struct CreateTopicRequest {
@@ -55,9 +185,9 @@ pub struct TopicResponse {
     pub topic_id: String,
     pub topic_name: String,
     pub cluster_id: String,
-    pub region: String,
+    pub region: Region,
     pub creation_time: usize,
-    pub state: String,
+    pub state: ResourceState,
     pub partitions: u32,
     pub multizone: Option<bool>,
     pub tcp_endpoint: String,
@@ -74,13 +204,13 @@ pub struct TopicResponse {
 pub struct ClusterResponse {
     pub cluster_id: String,
     pub name: String,
-    pub region: String,
+    pub region: Region,
     #[serde(rename = "type")]
-    pub type_name: String,
+    pub type_name: ClusterType,
     pub multizone: Option<bool>,
     pub tcp_endpoint: String,
     pub rest_endpoint: String,
-    pub state: String,
+    pub state: ResourceState,
     pub username: String,
     pub password: String,
     pub max_retention_size: usize,
@@ -98,13 +228,22 @@ pub enum CredentialState {
     Deleted,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum CredentialPermissions {
     All,
     Produce,
     Consume,
 }
+impl CredentialPermissions {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CredentialPermissions::All => "ALL",
+            CredentialPermissions::Produce => "PRODUCE",
+            CredentialPermissions::Consume => "CONSUME",
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize)]
 pub struct CreateCredentialRequest {
@@ -160,18 +299,34 @@ pub struct TopicStats {
     pub total_monthly_consume: u64,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub key: String,
+    pub value: String,
+}
+impl Header {
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub topic: String,
     pub value: String,
-    pub partition: u8,
+    pub partition: u64,
     pub key: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<Header>,
 }
 impl Message {
     pub fn new(
         topic: impl Into<String>,
         value: impl Into<String>,
-        partition: Option<u8>,
+        partition: Option<u64>,
         key: Option<impl Into<String>>,
     ) -> Self {
         Self {
@@ -179,34 +334,311 @@ impl Message {
             value: value.into(),
             partition: partition.unwrap_or(0),
             key: key.map(|k| k.into()).unwrap_or_default(),
+            headers: Vec::new(),
         }
     }
+
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push(Header::new(key, value));
+        self
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.iter().find(|h| h.key == key).map(|h| h.value.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ProduceResponse {
     pub topic: String,
-    pub partition: u8,
+    pub partition: u64,
     pub offset: u64,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ProduceRecordResponse {
+    topic: Option<String>,
+    partition: Option<u64>,
+    offset: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ProduceResult {
+    pub message: Message,
+    pub result: Result<ProduceResponse>,
+}
+
+#[derive(Debug)]
+pub struct ProduceReport {
+    pub results: Vec<ProduceResult>,
+}
+impl ProduceReport {
+    /// Pairs the responses in order with the messages which were sent, those rejected before sending keep their error.
+    pub(crate) fn new(
+        messages: Vec<Message>,
+        rejected: Vec<Option<Error>>,
+        responses: Vec<ProduceRecordResponse>,
+    ) -> Self {
+        let mut responses = responses.into_iter();
+        let mut rejected = rejected.into_iter();
+        let results = messages
+            .into_iter()
+            .map(|message| {
+                if let Some(err) = rejected.next().flatten() {
+                    return ProduceResult {
+                        message,
+                        result: Err(err),
+                    };
+                }
+                let result = match responses.next() {
+                    Some(ProduceRecordResponse {
+                        offset: Some(offset),
+                        error: None,
+                        topic,
+                        partition,
+                    }) => Ok(ProduceResponse {
+                        topic: topic.unwrap_or_else(|| message.topic.clone()),
+                        partition: partition.unwrap_or(message.partition),
+                        offset,
+                    }),
+                    Some(ProduceRecordResponse { error: Some(error), .. }) => {
+                        Err(Error::new(error.clone(), ErrorKind::ApiError(error)))
+                    }
+                    Some(_) => Err(Error::new("produce response without offset", ErrorKind::InvalidData)),
+                    None => Err(Error::new("missing produce response", ErrorKind::InvalidData)),
+                };
+                ProduceResult { message, result }
+            })
+            .collect();
+        Self { results }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|r| r.result.is_ok())
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = (&Message, &ProduceResponse)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.result.as_ref().ok().map(|resp| (&r.message, resp)))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (&Message, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.result.as_ref().err().map(|err| (&r.message, err)))
+    }
+
+    /// Consumes the report, returning only the messages which should be retried.
+    pub fn into_failed(self) -> Vec<Message> {
+        self.results
+            .into_iter()
+            .filter(|r| r.result.is_err())
+            .map(|r| r.message)
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FetchRequest {
+pub struct TopicPartitionOffset {
     pub topic: String,
-    pub partition: u8,
+    pub partition: u64,
     pub offset: u64,
 }
+impl TopicPartitionOffset {
+    pub fn new(topic: impl Into<String>, partition: u64, offset: u64) -> Self {
+        Self {
+            topic: topic.into(),
+            partition,
+            offset,
+        }
+    }
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FetchRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    #[serde(rename = "topicPartitionOffsets", default, skip_serializing_if = "Vec::is_empty")]
+    pub topic_partition_offsets: Vec<TopicPartitionOffset>,
+    /// How long the server waits for records before returning an empty response.
+    #[serde(skip)]
+    pub timeout: Option<Duration>,
+}
+impl FetchRequest {
+    pub fn new(topic: impl Into<String>, partition: u64, offset: u64) -> Self {
+        Self {
+            topic: Some(topic.into()),
+            partition: Some(partition),
+            offset: Some(offset),
+            ..Default::default()
+        }
+    }
+
+    pub fn partitions(offsets: impl IntoIterator<Item = TopicPartitionOffset>) -> Self {
+        Self {
+            topic_partition_offsets: offsets.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        let single = [self.topic.is_some(), self.partition.is_some(), self.offset.is_some()];
+        let valid = if self.topic_partition_offsets.is_empty() {
+            single.iter().all(|set| *set)
+        } else {
+            !single.iter().any(|set| *set)
+        };
+        if valid {
+            return Ok(());
+        }
+        Err(Error::new(
+            "fetch request needs either topic, partition and offset or topic partition offsets",
+            ErrorKind::InvalidData,
+        ))
+    }
+}
+#[derive(Debug, Serialize)]
+struct FetchBody<'a> {
+    #[serde(flatten)]
+    req: &'a FetchRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u64>,
+}
 #[derive(Debug, Clone, Deserialize)]
 pub struct FetchResponse {
     pub topic: String,
-    pub partition: u8,
+    pub partition: u64,
     pub offset: u64,
     pub key: String,
     pub value: String,
+    #[serde(default)]
+    pub headers: Vec<Header>,
+    /// Why the record could not be decompressed, it is then left as received.
+    #[serde(skip)]
+    pub invalid: Option<String>,
 }
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl FetchResponse {
+    /// Fails with `ErrorKind::InvalidData` when the record could not be decompressed.
+    pub fn check(&self) -> Result<()> {
+        invalid_record(&self.topic, self.partition, self.offset, self.invalid.as_deref())
+    }
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConsumeRequest {
-    pub topic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
+    #[serde(rename = "topicPattern", skip_serializing_if = "Option::is_none")]
+    pub topic_pattern: Option<String>,
+}
+impl ConsumeRequest {
+    pub fn new(topic: impl Into<String>) -> Self {
+        Self {
+            topic: Some(topic.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn topics<T: Into<String>>(topics: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            topics: topics.into_iter().map(|t| t.into()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn pattern(pattern: impl Into<String>) -> Self {
+        Self {
+            topic_pattern: Some(pattern.into()),
+            ..Default::default()
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let subscriptions =
+            self.topic.is_some() as u8 + !self.topics.is_empty() as u8 + self.topic_pattern.is_some() as u8;
+        if subscriptions != 1 {
+            return Err(Error::new(
+                "consume request needs exactly one of topic, topics or topic_pattern",
+                ErrorKind::InvalidData,
+            ));
+        }
+        Ok(())
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoOffsetReset {
+    Earliest,
+    Latest,
+    None,
+}
+impl AutoOffsetReset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AutoOffsetReset::Earliest => "earliest",
+            AutoOffsetReset::Latest => "latest",
+            AutoOffsetReset::None => "none",
+        }
+    }
+}
+#[derive(Debug, Clone)]
+pub struct ConsumeOptions {
+    pub auto_commit: bool,
+    pub auto_commit_interval: Option<Duration>,
+    pub auto_offset_reset: AutoOffsetReset,
+    pub instance_timeout: Option<Duration>,
+    pub timeout: Option<Duration>,
+}
+impl Default for ConsumeOptions {
+    fn default() -> Self {
+        Self {
+            auto_commit: false,
+            auto_commit_interval: None,
+            auto_offset_reset: AutoOffsetReset::Latest,
+            instance_timeout: None,
+            timeout: None,
+        }
+    }
+}
+impl ConsumeOptions {
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Kafka-Enable-Auto-Commit",
+            HeaderValue::from_static(if self.auto_commit { "true" } else { "false" }),
+        );
+        headers.insert(
+            "Kafka-Auto-Offset-Reset",
+            HeaderValue::from_static(self.auto_offset_reset.as_str()),
+        );
+        if let Some(interval) = self.auto_commit_interval {
+            headers.insert(
+                "Kafka-Auto-Commit-Interval",
+                HeaderValue::from(interval.as_millis() as u64),
+            );
+        }
+        if let Some(timeout) = self.instance_timeout {
+            headers.insert("Kafka-Instance-Timeout", HeaderValue::from(timeout.as_millis() as u64));
+        }
+        headers
+    }
+}
+#[derive(Serialize)]
+struct ConsumeBody<'a> {
+    #[serde(flatten)]
+    req: &'a ConsumeRequest,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u64>,
 }
 #[derive(Debug, Clone, Deserialize)]
 pub struct ConsumeResponse {
@@ -216,21 +648,114 @@ pub struct ConsumeResponse {
     pub timestamp: u64,
     pub topic: String,
     pub value: String,
+    #[serde(default)]
+    pub headers: Vec<Header>,
+    /// Why the record could not be decompressed or failed schema validation, it is then left as received.
+    #[serde(skip)]
+    pub invalid: Option<String>,
+}
+impl ConsumeResponse {
+    /// Fails with `ErrorKind::InvalidData` when the record could not be decompressed or failed schema validation.
+    pub fn check(&self) -> Result<()> {
+        invalid_record(&self.topic, self.partition, self.offset, self.invalid.as_deref())
+    }
+
+    /// Splits a batch consumed from several topics, keeping the order of records within each topic.
+    pub fn group_by_topic(records: Vec<ConsumeResponse>) -> BTreeMap<String, Vec<ConsumeResponse>> {
+        let mut topics: BTreeMap<String, Vec<ConsumeResponse>> = BTreeMap::new();
+        for record in records {
+            topics.entry(record.topic.clone()).or_default().push(record);
+        }
+        topics
+    }
 }
+/// The offset to commit next for every topic-partition.
+pub(crate) type NextOffsets = BTreeMap<(String, u64), u64>;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommitRequest {
     pub topic: String,
-    pub partition: u8,
+    pub partition: u64,
     pub offset: u64,
 }
 impl CommitRequest {
-    pub fn new(topic: impl Into<String>, partition: u8, offset: u64) -> Self {
+    pub fn new(topic: impl Into<String>, partition: u64, offset: u64) -> Self {
         Self {
             topic: topic.into(),
             partition,
             offset,
         }
     }
+
+    /// Builds one request per topic-partition, committing the offset after the highest record.
+    pub fn from_records<'a>(records: impl IntoIterator<Item = &'a ConsumeResponse>) -> Vec<Self> {
+        let mut offsets = NextOffsets::new();
+        for record in records {
+            Self::track(&mut offsets, record);
+        }
+        Self::from_offsets(&offsets)
+    }
+
+    /// Moves the offset to commit for the topic-partition of the record past it.
+    pub(crate) fn track(offsets: &mut NextOffsets, record: &ConsumeResponse) {
+        let offset = offsets.entry((record.topic.clone(), record.partition)).or_default();
+        *offset = (*offset).max(record.offset + 1);
+    }
+
+    pub(crate) fn from_offsets(offsets: &NextOffsets) -> Vec<Self> {
+        offsets
+            .iter()
+            .map(|((topic, partition), offset)| Self::new(topic, *partition, *offset))
+            .collect()
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopicPartition {
+    pub topic: String,
+    pub partition: u64,
+}
+impl TopicPartition {
+    pub fn new(topic: impl Into<String>, partition: u64) -> Self {
+        Self {
+            topic: topic.into(),
+            partition,
+        }
+    }
+}
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommittedOffset {
+    pub topic: String,
+    pub partition: u64,
+    pub offset: i64,
+    #[serde(default)]
+    pub metadata: String,
+}
+impl CommittedOffset {
+    /// The broker returns -1 when the group has not committed on the partition yet.
+    pub fn committed(&self) -> Option<u64> {
+        u64::try_from(self.offset).ok()
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetSpec {
+    Earliest,
+    Latest,
+    /// Unix timestamp in milliseconds; resolves to the first offset at or after it.
+    Timestamp(u64),
+}
+impl OffsetSpec {
+    fn path(&self) -> String {
+        match self {
+            OffsetSpec::Earliest => "earliest".to_string(),
+            OffsetSpec::Latest => "latest".to_string(),
+            OffsetSpec::Timestamp(timestamp) => timestamp.to_string(),
+        }
+    }
+}
+#[derive(Debug, Clone, Deserialize)]
+pub struct PartitionOffset {
+    pub topic: String,
+    pub partition: u64,
+    pub offset: i64,
 }
 #[derive(Debug, Clone, Deserialize)]
 pub struct CommitResponse {
@@ -278,9 +803,20 @@ pub trait KafkaService {
     async fn cluster_stats(&self, id: &str) -> Result<ClusterStats>;
     async fn topic_stats(&self, id: &str) -> Result<TopicStats>;
     async fn produce(&self, req: Vec<Message>) -> Result<Vec<ProduceResponse>>;
+    async fn produce_report(&self, req: Vec<Message>) -> Result<ProduceReport>;
     async fn fetch(&self, req: FetchRequest) -> Result<Vec<FetchResponse>>;
-    async fn consume(&self, group: &str, consumer: &str, req: ConsumeRequest) -> Result<Vec<ConsumeResponse>>;
+    async fn produce_get(&self, message: Message) -> Result<ProduceResponse>;
+    async fn fetch_get(&self, req: FetchRequest) -> Result<Vec<FetchResponse>>;
+    async fn consume(
+        &self,
+        group: &str,
+        consumer: &str,
+        req: ConsumeRequest,
+        options: ConsumeOptions,
+    ) -> Result<Vec<ConsumeResponse>>;
     async fn commit(&self, group: &str, consumer: &str, req: Vec<CommitRequest>) -> Result<CommitResponse>;
+    async fn committed(&self, group: &str, consumer: &str, req: Vec<TopicPartition>) -> Result<Vec<CommittedOffset>>;
+    async fn partition_offsets(&self, at: OffsetSpec, req: Vec<TopicPartition>) -> Result<Vec<PartitionOffset>>;
     async fn list_consumers(&self) -> Result<Vec<GroupInstance>>;
     async fn delete_consumer(&self, group: &str, consumer: &str) -> Result<DeleteConsumerResponse>;
 }
@@ -370,21 +906,109 @@ impl<'client> KafkaService for Handler<'client> {
     }
 
     async fn produce(&self, req: Vec<Message>) -> Result<Vec<ProduceResponse>> {
-        self.client.post(&self.url, Option::None::<&()>, Some(&req), None).await
+        let body = self.outgoing(&req)?;
+        self.client
+            .post(&self.url, Option::None::<&()>, Some(&*body), None)
+            .await
+    }
+
+    async fn produce_report(&self, req: Vec<Message>) -> Result<ProduceReport> {
+        let mut body = Vec::with_capacity(req.len());
+        let mut rejected = Vec::with_capacity(req.len());
+        for message in &req {
+            match self.prepare(message) {
+                Ok(message) => {
+                    body.push(message);
+                    rejected.push(None);
+                }
+                Err(err) => rejected.push(Some(err)),
+            }
+        }
+        let responses: Vec<ProduceRecordResponse> = if body.is_empty() {
+            Vec::new()
+        } else {
+            self.client
+                .post(&self.url, Option::None::<&()>, Some(&body), None)
+                .await?
+        };
+        Ok(ProduceReport::new(req, rejected, responses))
     }
 
     async fn fetch(&self, req: FetchRequest) -> Result<Vec<FetchResponse>> {
-        self.client.post(&self.url, Option::None::<&()>, Some(&req), None).await
+        req.validate()?;
+        let body = FetchBody {
+            req: &req,
+            timeout: req.timeout.map(|timeout| timeout.as_millis() as u64),
+        };
+        let records: Vec<FetchResponse> = self
+            .client
+            .post(&self.url, Option::None::<&()>, Some(&body), None)
+            .await?;
+        Ok(self.fetched(records))
+    }
+
+    async fn produce_get(&self, message: Message) -> Result<ProduceResponse> {
+        let outgoing = self.prepare(&message)?;
+        if !outgoing.headers.is_empty() {
+            return Err(Error::new(
+                "produce over GET cannot send headers",
+                ErrorKind::InvalidData,
+            ));
+        }
+        let mut url = self.get_url(&[&outgoing.topic, &outgoing.value])?;
+        {
+            let mut query = url.query_pairs_mut();
+            if !outgoing.key.is_empty() {
+                query.append_pair("key", &outgoing.key);
+            }
+            query.append_pair("partition", &outgoing.partition.to_string());
+        }
+        check_url_length(&url)?;
+        let response: ProduceRecordResponse = self.client.get(url, Option::None::<&()>).await?;
+        let mut report = ProduceReport::new(vec![message], vec![None], vec![response]);
+        report.results.remove(0).result
+    }
+
+    async fn fetch_get(&self, req: FetchRequest) -> Result<Vec<FetchResponse>> {
+        let (topic, partition, offset) = match (&req.topic, req.partition, req.offset) {
+            (Some(topic), Some(partition), Some(offset)) if req.topic_partition_offsets.is_empty() => {
+                (topic, partition, offset)
+            }
+            _ => {
+                return Err(Error::new(
+                    "fetch over GET needs a single topic, partition and offset",
+                    ErrorKind::InvalidData,
+                ))
+            }
+        };
+        let mut url = self.get_url(&[topic, &partition.to_string(), &offset.to_string()])?;
+        if let Some(timeout) = req.timeout {
+            url.query_pairs_mut()
+                .append_pair("timeout", &timeout.as_millis().to_string());
+        }
+        check_url_length(&url)?;
+        let records: Vec<FetchResponse> = self.client.get(url, Option::None::<&()>).await?;
+        Ok(self.fetched(records))
     }
 
-    async fn consume(&self, group: &str, consumer: &str, req: ConsumeRequest) -> Result<Vec<ConsumeResponse>> {
+    async fn consume(
+        &self,
+        group: &str,
+        consumer: &str,
+        req: ConsumeRequest,
+        options: ConsumeOptions,
+    ) -> Result<Vec<ConsumeResponse>> {
+        req.validate()?;
         let url = format!("{}/{}/{}", &self.url, group, consumer);
-        let mut headers = HeaderMap::new();
-        headers.insert("Kafka-Enable-Auto-Commit", HeaderValue::from_str("false").unwrap());
-        headers.insert("Kafka-Auto-Offset-Reset", HeaderValue::from_str("latest").unwrap());
-        self.client
-            .post(&url, Option::None::<&()>, Some(&req), Some(headers))
-            .await
+        let body = ConsumeBody {
+            req: &req,
+            timeout: options.timeout.map(|timeout| timeout.as_millis() as u64),
+        };
+        let records: Vec<ConsumeResponse> = self
+            .client
+            .post(&url, Option::None::<&()>, Some(&body), Some(options.headers()))
+            .await?;
+        Ok(self.incoming(records))
     }
 
     async fn commit(&self, group: &str, consumer: &str, req: Vec<CommitRequest>) -> Result<CommitResponse> {
@@ -392,6 +1016,16 @@ impl<'client> KafkaService for Handler<'client> {
         self.client.post(&url, Option::None::<&()>, Some(&req), None).await
     }
 
+    async fn committed(&self, group: &str, consumer: &str, req: Vec<TopicPartition>) -> Result<Vec<CommittedOffset>> {
+        let url = format!("{}committed/{}/{}", &self.url, group, consumer);
+        self.client.post(&url, Option::None::<&()>, Some(&req), None).await
+    }
+
+    async fn partition_offsets(&self, at: OffsetSpec, req: Vec<TopicPartition>) -> Result<Vec<PartitionOffset>> {
+        let url = format!("{}offsets/{}", &self.url, at.path());
+        self.client.post(&url, Option::None::<&()>, Some(&req), None).await
+    }
+
     async fn list_consumers(&self) -> Result<Vec<GroupInstance>> {
         let url = format!("{}consumers", &self.url);
         self.client.get(&url, Option::None::<&()>).await
@@ -402,3 +1036,150 @@ impl<'client> KafkaService for Handler<'client> {
         self.client.delete(&url, Option::None::<&()>).await
     }
 }
+
+impl Handler<'_> {
+    /// Fetches the records of a partition from offset `from` up to, not including, offset `to`.
+    pub async fn fetch_range(&self, topic: &str, partition: u64, from: u64, to: u64) -> Result<Vec<FetchResponse>> {
+        let mut records = Vec::new();
+        self.fetch_range_pages(topic, partition, from, to, |page| {
+            records.extend(page);
+            Ok(())
+        })
+        .await?;
+        Ok(records)
+    }
+
+    /// Same as `fetch_range`, passing every page to `on_page` instead of collecting the whole range.
+    pub async fn fetch_range_pages(
+        &self,
+        topic: &str,
+        partition: u64,
+        from: u64,
+        to: u64,
+        mut on_page: impl FnMut(Vec<FetchResponse>) -> Result<()>,
+    ) -> Result<()> {
+        let mut offset = from;
+        while offset < to {
+            let page = self.fetch(FetchRequest::new(topic, partition, offset)).await?;
+            let last = match page.iter().map(|r| r.offset).max() {
+                Some(last) => last,
+                None => break,
+            };
+            on_page(
+                page.into_iter()
+                    .filter(|r| r.offset >= offset && r.offset < to)
+                    .collect(),
+            )?;
+            offset = last + 1;
+        }
+        Ok(())
+    }
+
+    fn outgoing<'a>(&self, req: &'a [Message]) -> Result<Vec<Cow<'a, Message>>> {
+        req.iter().map(|message| self.prepare(message)).collect()
+    }
+
+    /// Validates the message against the schema registry and compresses it, as configured on the client.
+    fn prepare<'a>(&self, message: &'a Message) -> Result<Cow<'a, Message>> {
+        #[cfg(feature = "schema")]
+        if let Some(registry) = self.client.schema_registry() {
+            registry.validate(&message.topic, &message.value)?;
+        }
+        #[cfg(feature = "compression")]
+        if let Some(compression) = self.client.compression() {
+            return Ok(Cow::Owned(compression.compress_message(message)?));
+        }
+        Ok(Cow::Borrowed(message))
+    }
+
+    /// Produces messages which already went through `prepare`, without preparing them again.
+    pub(crate) async fn produce_prepared(&self, req: Vec<Message>) -> Result<ProduceReport> {
+        let rejected = req.iter().map(|_| None).collect();
+        let responses: Vec<ProduceRecordResponse> = if req.is_empty() {
+            Vec::new()
+        } else {
+            self.client
+                .post(&self.url, Option::None::<&()>, Some(&req), None)
+                .await?
+        };
+        Ok(ProduceReport::new(req, rejected, responses))
+    }
+
+    // the server has already moved past the batch, so a bad record is flagged instead of failing the others
+    fn fetched(&self, records: Vec<FetchResponse>) -> Vec<FetchResponse> {
+        #[cfg(feature = "compression")]
+        let records = records
+            .into_iter()
+            .map(|mut record| {
+                record.invalid = decompress(&mut record.headers, &mut record.value);
+                record
+            })
+            .collect();
+        records
+    }
+
+    /// Appends percent-encoded path segments to the handler url.
+    fn get_url(&self, segments: &[&str]) -> Result<url::Url> {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .map_err(|_| Error::new(format!("{} cannot have a path", self.url), ErrorKind::Internal))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+
+    fn incoming(&self, records: Vec<ConsumeResponse>) -> Vec<ConsumeResponse> {
+        #[cfg(feature = "compression")]
+        let records: Vec<ConsumeResponse> = records
+            .into_iter()
+            .map(|mut record| {
+                record.invalid = decompress(&mut record.headers, &mut record.value);
+                record
+            })
+            .collect();
+        #[cfg(feature = "schema")]
+        let records = match self.client.schema_registry() {
+            Some(registry) => records
+                .into_iter()
+                .map(|mut record| {
+                    if record.invalid.is_none() {
+                        record.invalid = registry
+                            .validate(&record.topic, &record.value)
+                            .err()
+                            .map(|err| err.to_string());
+                    }
+                    record
+                })
+                .collect(),
+            None => records,
+        };
+        records
+    }
+}
+
+#[cfg(feature = "compression")]
+fn decompress(headers: &mut Vec<Header>, value: &mut String) -> Option<String> {
+    decompress_value(headers, value).err().map(|err| err.to_string())
+}
+
+fn invalid_record(topic: &str, partition: u64, offset: u64, invalid: Option<&str>) -> Result<()> {
+    match invalid {
+        Some(reason) => Err(Error::new(
+            format!("record {}/{}/{} is invalid: {}", topic, partition, offset, reason),
+            ErrorKind::InvalidData,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Only the path and query are measured, the credentials in the base url never go on the request line.
+pub(crate) fn check_url_length(url: &url::Url) -> Result<()> {
+    let length = url[url::Position::BeforePath..url::Position::AfterQuery].len();
+    if length > MAX_GET_URL_LENGTH {
+        return Err(Error::new(
+            format!("path and query of {} bytes exceed {} bytes", length, MAX_GET_URL_LENGTH),
+            ErrorKind::InvalidData,
+        ));
+    }
+    Ok(())
+}
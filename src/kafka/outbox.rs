@@ -0,0 +1,219 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+use super::stream::wait_or_stop;
+use super::{KafkaService, Message, ProduceResponse, StopHandle};
+use crate::error::{Error, Result};
+use crate::Client;
+
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub id: u64,
+    pub message: Message,
+    pub attempts: u32,
+}
+
+/// Pending messages are written by the application in the same transaction as its business data,
+/// the relay only reads them back and records the outcome of producing them.
+#[async_trait]
+pub trait OutboxStore: Send + Sync {
+    /// Oldest entries first, skipping those which already failed `max_attempts` times.
+    async fn pending(&self, limit: usize, max_attempts: u32) -> Result<Vec<OutboxEntry>>;
+    async fn mark_sent(&self, id: u64, response: &ProduceResponse) -> Result<()>;
+    async fn mark_failed(&self, id: u64, err: &Error) -> Result<()>;
+}
+
+#[derive(Debug)]
+struct MemoryRow {
+    entry: OutboxEntry,
+    sent: Option<ProduceResponse>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryOutbox {
+    rows: Mutex<Vec<MemoryRow>>,
+}
+
+impl MemoryOutbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, message: Message) -> u64 {
+        let mut rows = self.rows.lock().unwrap();
+        let id = rows.len() as u64 + 1;
+        rows.push(MemoryRow {
+            entry: OutboxEntry {
+                id,
+                message,
+                attempts: 0,
+            },
+            sent: None,
+            error: None,
+        });
+        id
+    }
+
+    pub fn sent(&self, id: u64) -> Option<ProduceResponse> {
+        let rows = self.rows.lock().unwrap();
+        rows.iter().find(|r| r.entry.id == id).and_then(|r| r.sent.clone())
+    }
+
+    pub fn last_error(&self, id: u64) -> Option<String> {
+        let rows = self.rows.lock().unwrap();
+        rows.iter().find(|r| r.entry.id == id).and_then(|r| r.error.clone())
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut MemoryRow)) {
+        let mut rows = self.rows.lock().unwrap();
+        if let Some(row) = rows.iter_mut().find(|r| r.entry.id == id) {
+            update(row);
+        }
+    }
+}
+
+#[async_trait]
+impl OutboxStore for MemoryOutbox {
+    async fn pending(&self, limit: usize, max_attempts: u32) -> Result<Vec<OutboxEntry>> {
+        let rows = self.rows.lock().unwrap();
+        Ok(rows
+            .iter()
+            .filter(|r| r.sent.is_none() && r.entry.attempts < max_attempts)
+            .take(limit)
+            .map(|r| r.entry.clone())
+            .collect())
+    }
+
+    async fn mark_sent(&self, id: u64, response: &ProduceResponse) -> Result<()> {
+        self.update(id, |row| row.sent = Some(response.clone()));
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: u64, err: &Error) -> Result<()> {
+        self.update(id, |row| {
+            row.entry.attempts += 1;
+            row.error = Some(err.to_string());
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    pub batch_size: usize,
+    pub poll_interval: Duration,
+    pub max_attempts: u32,
+    pub max_retries: u32,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            poll_interval: Duration::from_secs(1),
+            max_attempts: 5,
+            max_retries: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelayReport {
+    pub sent: usize,
+    pub failed: usize,
+}
+
+/// Drains the outbox through `produce`. Failed entries stay pending and are retried on the next
+/// polls until they have failed `max_attempts` times; a failed entry does not hold back the later ones.
+pub struct OutboxRelay<S> {
+    client: Client,
+    store: S,
+    config: OutboxConfig,
+    stop: StopHandle,
+    stopped: watch::Receiver<bool>,
+}
+
+impl<S: OutboxStore> OutboxRelay<S> {
+    pub fn new(client: Client, store: S, config: OutboxConfig) -> Self {
+        let (stop, stopped) = StopHandle::new();
+        Self {
+            client,
+            store,
+            config,
+            stop,
+            stopped,
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    pub async fn relay_once(&self) -> Result<RelayReport> {
+        let entries = self
+            .store
+            .pending(self.config.batch_size, self.config.max_attempts)
+            .await?;
+        let mut report = RelayReport::default();
+        if entries.is_empty() {
+            return Ok(report);
+        }
+        let messages = entries.iter().map(|e| e.message.clone()).collect();
+        match self.client.producer().produce_report(messages).await {
+            Ok(produced) => {
+                for (entry, produced) in entries.iter().zip(produced.results) {
+                    match produced.result {
+                        Ok(response) => {
+                            self.store.mark_sent(entry.id, &response).await?;
+                            report.sent += 1;
+                        }
+                        Err(err) => {
+                            self.store.mark_failed(entry.id, &err).await?;
+                            report.failed += 1;
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                for entry in &entries {
+                    self.store.mark_failed(entry.id, &err).await?;
+                }
+                report.failed = entries.len();
+            }
+        }
+        Ok(report)
+    }
+
+    /// Relays until stopped, polling again right away while full batches are being sent.
+    /// Fails after `max_retries` consecutive store errors.
+    pub async fn run(&self) -> Result<()> {
+        let mut stopped = self.stopped.clone();
+        let mut failures = 0;
+        while !*stopped.borrow() {
+            match self.relay_once().await {
+                Ok(report) => {
+                    failures = 0;
+                    if report.sent + report.failed >= self.config.batch_size && report.failed == 0 {
+                        continue;
+                    }
+                }
+                Err(err) => {
+                    failures += 1;
+                    if failures > self.config.max_retries {
+                        return Err(err);
+                    }
+                }
+            }
+            wait_or_stop(&mut stopped, self.config.poll_interval).await;
+        }
+        Ok(())
+    }
+}
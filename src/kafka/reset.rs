@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use super::{CommitRequest, KafkaService, OffsetSpec, PartitionOffset, TopicPartition};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Handler;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetTarget {
+    Earliest,
+    Latest,
+    Offset(u64),
+    /// Unix timestamp in milliseconds; partitions with nothing after it are moved to the latest offset.
+    Timestamp(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetReset {
+    pub topic: String,
+    pub partition: u64,
+    pub current: Option<u64>,
+    pub target: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResetPlan {
+    pub group: String,
+    pub consumer: String,
+    pub resets: Vec<OffsetReset>,
+    pub applied: bool,
+}
+
+impl ResetPlan {
+    pub fn changed(&self) -> impl Iterator<Item = &OffsetReset> {
+        self.resets.iter().filter(|r| r.current != Some(r.target))
+    }
+}
+
+type Offsets = BTreeMap<(String, u64), u64>;
+
+fn offsets(offsets: Vec<PartitionOffset>) -> Offsets {
+    offsets
+        .into_iter()
+        .filter_map(|o| Some(((o.topic, o.partition), u64::try_from(o.offset).ok()?)))
+        .collect()
+}
+
+impl Handler<'_> {
+    /// Moves the committed offsets of the group on the given partitions, see `ConsumerInstance::partitions` to list
+    /// those of the subscribed topics. Consumers of the group should be stopped first, or they will commit over the reset.
+    ///
+    /// With `dry_run` nothing is committed and the plan shows the current and target offset of each partition.
+    /// Fails with `ErrorKind::InvalidData` when an explicit offset is outside the range still in the log.
+    pub async fn reset_offsets(
+        &self,
+        group: &str,
+        consumer: &str,
+        partitions: Vec<TopicPartition>,
+        target: ResetTarget,
+        dry_run: bool,
+    ) -> Result<ResetPlan> {
+        if partitions.is_empty() {
+            return Err(Error::new("no partitions to reset", ErrorKind::InvalidData));
+        }
+        let committed = self.committed(group, consumer, partitions.clone()).await?;
+        let current: BTreeMap<(String, u64), u64> = committed
+            .iter()
+            .filter_map(|o| Some(((o.topic.clone(), o.partition), o.committed()?)))
+            .collect();
+        let earliest = offsets(self.partition_offsets(OffsetSpec::Earliest, partitions.clone()).await?);
+        let latest = offsets(self.partition_offsets(OffsetSpec::Latest, partitions.clone()).await?);
+        let at = match target {
+            ResetTarget::Timestamp(timestamp) => Some(offsets(
+                self.partition_offsets(OffsetSpec::Timestamp(timestamp), partitions.clone())
+                    .await?,
+            )),
+            _ => None,
+        };
+
+        let mut resets = Vec::with_capacity(partitions.len());
+        for partition in partitions {
+            let key = (partition.topic, partition.partition);
+            let (start, end) = match (earliest.get(&key), latest.get(&key)) {
+                (Some(start), Some(end)) => (*start, *end),
+                _ => {
+                    return Err(Error::new(
+                        format!("no offsets for {}/{}", key.0, key.1),
+                        ErrorKind::InvalidData,
+                    ))
+                }
+            };
+            let target = match target {
+                ResetTarget::Earliest => start,
+                ResetTarget::Latest => end,
+                ResetTarget::Offset(offset) if (start..=end).contains(&offset) => offset,
+                ResetTarget::Offset(offset) => {
+                    return Err(Error::new(
+                        format!(
+                            "offset {} of {}/{} is outside the log, which goes from {} to {}",
+                            offset, key.0, key.1, start, end
+                        ),
+                        ErrorKind::InvalidData,
+                    ))
+                }
+                ResetTarget::Timestamp(_) => at.as_ref().and_then(|at| at.get(&key)).copied().unwrap_or(end),
+            };
+            resets.push(OffsetReset {
+                current: current.get(&key).copied(),
+                topic: key.0,
+                partition: key.1,
+                target,
+            });
+        }
+
+        let mut plan = ResetPlan {
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            resets,
+            applied: false,
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+        let req: Vec<CommitRequest> = plan
+            .changed()
+            .map(|r| CommitRequest::new(&r.topic, r.partition, r.target))
+            .collect();
+        if !req.is_empty() {
+            let response = self.commit(group, consumer, req).await?;
+            if !response.error.is_empty() {
+                return Err(Error::new(
+                    format!("offset reset failed: {}", response.error),
+                    ErrorKind::ApiError(response.error),
+                ));
+            }
+        }
+        plan.applied = true;
+        Ok(plan)
+    }
+}
@@ -0,0 +1,231 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use tokio::sync::watch;
+
+use super::dead_letter::{dead_letter_message, produce_all};
+use super::stream::wait_or_stop;
+use super::{
+    ConsumeRequest, ConsumeResponse, ConsumerRunner, DeadLetterPolicy, Header, Message, RecordHandler, RunnerConfig,
+    StopHandle,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Client;
+
+pub const RETRY_ATTEMPT_HEADER: &str = "retry-attempt";
+pub const RETRY_NOT_BEFORE_HEADER: &str = "retry-not-before";
+pub const RETRY_ERROR_HEADER: &str = "retry-error";
+pub const RETRY_SOURCE_TOPIC_HEADER: &str = "retry-source-topic";
+
+const RETRY_HEADERS: [&str; 4] = [
+    RETRY_ATTEMPT_HEADER,
+    RETRY_NOT_BEFORE_HEADER,
+    RETRY_ERROR_HEADER,
+    RETRY_SOURCE_TOPIC_HEADER,
+];
+
+#[derive(Debug, Clone)]
+pub struct RetryTier {
+    pub topic: String,
+    pub delay: Duration,
+}
+
+impl RetryTier {
+    pub fn new(topic: impl Into<String>, delay: Duration) -> Self {
+        Self {
+            topic: topic.into(),
+            delay,
+        }
+    }
+}
+
+/// Tiers of retry topics a failed record moves through, each one redelivering it after its delay.
+/// Once every tier has failed the record goes to the topic of the runner's dead-letter policy, if any.
+#[derive(Debug, Clone)]
+pub struct RetryTopics {
+    pub tiers: Vec<RetryTier>,
+}
+
+impl RetryTopics {
+    pub fn new(tiers: Vec<RetryTier>) -> Self {
+        Self { tiers }
+    }
+
+    /// Number of retry tiers the record has already been through.
+    pub fn attempt(record: &ConsumeResponse) -> usize {
+        header(&record.headers, RETRY_ATTEMPT_HEADER)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Time left until the record may be redelivered.
+    pub fn due_in(record: &ConsumeResponse) -> Duration {
+        let not_before = header(&record.headers, RETRY_NOT_BEFORE_HEADER).and_then(|value| value.parse::<u64>().ok());
+        match not_before {
+            Some(not_before) => Duration::from_millis(not_before.saturating_sub(now_millis())),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Builds the message for the next tier, or for the dead-letter topic once the tiers are exhausted.
+    pub fn next_message(
+        &self,
+        record: &ConsumeResponse,
+        err: &Error,
+        dead_letter: Option<&DeadLetterPolicy>,
+    ) -> Option<Message> {
+        let attempt = Self::attempt(record);
+        let tier = match self.tiers.get(attempt) {
+            Some(tier) => tier,
+            None => {
+                return dead_letter.map(|policy| dead_letter_message(&policy.topic, record, err, attempt as u32 + 1));
+            }
+        };
+        let source = header(&record.headers, RETRY_SOURCE_TOPIC_HEADER).unwrap_or(&record.topic);
+        let not_before = now_millis() + tier.delay.as_millis() as u64;
+        let mut message = Message::new(&tier.topic, &record.value, None, Some(&record.key));
+        message.headers = record
+            .headers
+            .iter()
+            .filter(|h| !RETRY_HEADERS.contains(&h.key.as_str()))
+            .cloned()
+            .collect();
+        Some(
+            message
+                .with_header(RETRY_ATTEMPT_HEADER, (attempt + 1).to_string())
+                .with_header(RETRY_NOT_BEFORE_HEADER, not_before.to_string())
+                .with_header(RETRY_ERROR_HEADER, err.to_string())
+                .with_header(RETRY_SOURCE_TOPIC_HEADER, source),
+        )
+    }
+
+    /// Fails when a record has exhausted all tiers and there is no dead-letter topic to move it to.
+    pub async fn republish(
+        &self,
+        client: &Client,
+        records: &[ConsumeResponse],
+        err: &Error,
+        dead_letter: Option<&DeadLetterPolicy>,
+    ) -> Result<()> {
+        let mut messages = Vec::with_capacity(records.len());
+        for record in records {
+            match self.next_message(record, err, dead_letter) {
+                Some(message) => messages.push(message),
+                None => {
+                    return Err(Error::new(
+                        format!(
+                            "record {}/{}/{} exhausted its retries: {}",
+                            record.topic, record.partition, record.offset, err
+                        ),
+                        ErrorKind::ApiError(err.to_string()),
+                    ))
+                }
+            }
+        }
+        produce_all(client, messages, "retry topics").await
+    }
+}
+
+fn header<'a>(headers: &'a [Header], key: &str) -> Option<&'a str> {
+    headers.iter().find(|h| h.key == key).map(|h| h.value.as_str())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Runs a `ConsumerRunner` for the source topic and one for each retry tier, all sharing the handler.
+/// Records from a retry tier are held back until their delay has passed.
+pub struct RetryConsumer {
+    runners: Vec<ConsumerRunner>,
+    stop: StopHandle,
+    stopped: watch::Receiver<bool>,
+}
+
+impl RetryConsumer {
+    pub fn new(
+        client: Client,
+        group: impl Into<String>,
+        consumer: impl Into<String>,
+        req: ConsumeRequest,
+        retry_topics: RetryTopics,
+        config: RunnerConfig,
+    ) -> Self {
+        let (group, consumer) = (group.into(), consumer.into());
+        let (stop, stopped) = StopHandle::new();
+        let mut runners = Vec::with_capacity(retry_topics.tiers.len() + 1);
+        let source = RunnerConfig {
+            retry_topics: Some(retry_topics.clone()),
+            ..config.clone()
+        };
+        runners.push(ConsumerRunner::new(client.clone(), &group, &consumer, req, source).stop_with(&stop));
+        for tier in &retry_topics.tiers {
+            // records are retried through the tiers, so the tier runners only use the dead-letter topic
+            let dead_letter = config.dead_letter.clone().map(|policy| DeadLetterPolicy {
+                max_attempts: 1,
+                ..policy
+            });
+            let mut config = RunnerConfig {
+                retry_topics: Some(retry_topics.clone()),
+                dead_letter,
+                ..config.clone()
+            };
+            // the instance must outlive a record held back for the tier delay
+            let timeout = tier.delay + Duration::from_secs(30);
+            config.options.instance_timeout = Some(config.options.instance_timeout.unwrap_or_default().max(timeout));
+            let name = format!("{}-{}", consumer, tier.topic);
+            let runner = ConsumerRunner::new(client.clone(), &group, name, ConsumeRequest::new(&tier.topic), config);
+            runners.push(runner.stop_with(&stop));
+        }
+        Self { runners, stop, stopped }
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Runs until stopped; when one runner fails the others are stopped and the first error is returned.
+    pub async fn run(&self, handler: impl RecordHandler) -> Result<()> {
+        let handler = Delayed {
+            handler: &handler,
+            stopped: self.stopped.clone(),
+        };
+        let runs = self.runners.iter().map(|runner| async {
+            let result = runner.run(&handler).await;
+            if result.is_err() {
+                self.stop.stop();
+            }
+            result
+        });
+        join_all(runs)
+            .await
+            .into_iter()
+            .filter_map(|result| result.err())
+            .find(|err| !matches!(err.kind(), ErrorKind::Cancelled))
+            .map_or(Ok(()), Err)
+    }
+}
+
+struct Delayed<'a, H> {
+    handler: &'a H,
+    stopped: watch::Receiver<bool>,
+}
+
+#[async_trait]
+impl<H: RecordHandler> RecordHandler for &Delayed<'_, H> {
+    async fn handle(&self, record: &ConsumeResponse) -> Result<()> {
+        let wait = RetryTopics::due_in(record);
+        if !wait.is_zero() {
+            let mut stopped = self.stopped.clone();
+            wait_or_stop(&mut stopped, wait).await;
+            if *stopped.borrow() {
+                return Err(Error::new("retry consumer stopped", ErrorKind::Cancelled));
+            }
+        }
+        self.handler.handle(record).await
+    }
+}
@@ -0,0 +1,295 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use super::dead_letter::attempt;
+use super::stream::wait_or_stop;
+use super::{
+    CommitRequest, ConsumeOptions, ConsumeRequest, ConsumeResponse, DeadLetterPolicy, KafkaService, NextOffsets,
+    RetryTopics, StopHandle,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::Client;
+
+#[async_trait]
+pub trait RecordHandler: Send + Sync {
+    async fn handle(&self, record: &ConsumeResponse) -> Result<()>;
+}
+
+#[async_trait]
+impl<F, Fut> RecordHandler for F
+where
+    F: Fn(ConsumeResponse) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<()>> + Send,
+{
+    async fn handle(&self, record: &ConsumeResponse) -> Result<()> {
+        self(record.clone()).await
+    }
+}
+
+#[async_trait]
+pub trait BatchHandler: Send + Sync {
+    async fn handle_batch(&self, records: &[ConsumeResponse]) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct RunnerConfig {
+    pub options: ConsumeOptions,
+    pub commit_interval: Duration,
+    pub idle_backoff: Duration,
+    pub max_retries: u32,
+    pub dead_letter: Option<DeadLetterPolicy>,
+    pub retry_topics: Option<RetryTopics>,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        Self {
+            options: ConsumeOptions::default(),
+            commit_interval: Duration::from_secs(5),
+            idle_backoff: Duration::from_millis(500),
+            max_retries: 5,
+            dead_letter: None,
+            retry_topics: None,
+        }
+    }
+}
+
+/// The offsets to commit for the records processed since the last commit.
+#[derive(Debug, Default)]
+pub(crate) struct OffsetTracker {
+    next: NextOffsets,
+}
+
+impl OffsetTracker {
+    pub(crate) fn processed(&mut self, record: &ConsumeResponse) {
+        CommitRequest::track(&mut self.next, record);
+    }
+
+    pub(crate) async fn commit(&mut self, client: &Client, group: &str, consumer: &str) -> Result<()> {
+        if self.next.is_empty() {
+            return Ok(());
+        }
+        let req = CommitRequest::from_offsets(&self.next);
+        let response = client.handler("").commit(group, consumer, req).await?;
+        if !response.error.is_empty() {
+            return Err(Error::new(
+                format!("commit failed: {}", response.error),
+                ErrorKind::ApiError(response.error),
+            ));
+        }
+        self.next.clear();
+        Ok(())
+    }
+}
+
+/// Consumes with auto commit disabled and commits the offsets of records only after the handler succeeded.
+///
+/// When the handler fails and no dead-letter policy is configured, the progress made so far is committed,
+/// the consumer instance is deleted so that the next run restarts from the committed offsets,
+/// and the error is returned. Records that failed validation on the way in fail the same way,
+/// without reaching the handler.
+pub struct ConsumerRunner {
+    client: Client,
+    group: String,
+    consumer: String,
+    req: ConsumeRequest,
+    config: RunnerConfig,
+    stop: StopHandle,
+    stopped: watch::Receiver<bool>,
+}
+
+impl ConsumerRunner {
+    pub fn new(
+        client: Client,
+        group: impl Into<String>,
+        consumer: impl Into<String>,
+        req: ConsumeRequest,
+        mut config: RunnerConfig,
+    ) -> Self {
+        config.options.auto_commit = false;
+        let (stop, stopped) = StopHandle::new();
+        Self {
+            client,
+            group: group.into(),
+            consumer: consumer.into(),
+            req,
+            config,
+            stop,
+            stopped,
+        }
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    pub async fn run(&self, handler: impl RecordHandler) -> Result<()> {
+        let process = PerRecord {
+            handler,
+            failure: self.failure(),
+        };
+        self.run_loop(&process).await
+    }
+
+    pub async fn run_batch(&self, handler: impl BatchHandler) -> Result<()> {
+        let process = PerBatch {
+            handler,
+            failure: self.failure(),
+        };
+        self.run_loop(&process).await
+    }
+
+    pub(crate) fn stop_with(mut self, stop: &StopHandle) -> Self {
+        self.stopped = stop.subscribe();
+        self.stop = stop.clone();
+        self
+    }
+
+    fn failure(&self) -> Failure<'_> {
+        Failure {
+            client: &self.client,
+            dead_letter: self.config.dead_letter.as_ref(),
+            retry_topics: self.config.retry_topics.as_ref(),
+        }
+    }
+
+    async fn run_loop(&self, process: &dyn Process) -> Result<()> {
+        let mut stopped = self.stopped.clone();
+        let mut tracker = OffsetTracker::default();
+        let mut last_commit = Instant::now();
+        let mut failures = 0;
+        let result = loop {
+            if *stopped.borrow() {
+                break Ok(());
+            }
+            let consumed = self
+                .client
+                .consumer()
+                .consume(
+                    &self.group,
+                    &self.consumer,
+                    self.req.clone(),
+                    self.config.options.clone(),
+                )
+                .await;
+            let records = match consumed {
+                Ok(records) => {
+                    failures = 0;
+                    records
+                }
+                Err(err) => {
+                    failures += 1;
+                    if failures > self.config.max_retries {
+                        break Err(err);
+                    }
+                    wait_or_stop(&mut stopped, self.config.idle_backoff.saturating_mul(failures)).await;
+                    continue;
+                }
+            };
+            if records.is_empty() {
+                wait_or_stop(&mut stopped, self.config.idle_backoff).await;
+            } else if let Err(err) = process.process(&records, &mut tracker).await {
+                tracker.commit(&self.client, &self.group, &self.consumer).await?;
+                let _ = self
+                    .client
+                    .handler("")
+                    .delete_consumer(&self.group, &self.consumer)
+                    .await;
+                return Err(err);
+            }
+            if last_commit.elapsed() >= self.config.commit_interval {
+                tracker.commit(&self.client, &self.group, &self.consumer).await?;
+                last_commit = Instant::now();
+            }
+        };
+        let committed = tracker.commit(&self.client, &self.group, &self.consumer).await;
+        result.and(committed)
+    }
+}
+
+#[async_trait]
+trait Process: Send + Sync {
+    async fn process(&self, records: &[ConsumeResponse], tracker: &mut OffsetTracker) -> Result<()>;
+}
+
+// Failed records are retried in place as the dead-letter policy allows, then handed to the retry
+// topics or the dead-letter topic, so they count as processed and the runner moves on.
+struct Failure<'a> {
+    client: &'a Client,
+    dead_letter: Option<&'a DeadLetterPolicy>,
+    retry_topics: Option<&'a RetryTopics>,
+}
+
+impl Failure<'_> {
+    async fn recover(&self, records: &[ConsumeResponse], err: Error, attempts: u32) -> Result<()> {
+        if matches!(err.kind(), ErrorKind::Cancelled) {
+            return Err(err);
+        }
+        if let Some(retry_topics) = self.retry_topics {
+            return retry_topics
+                .republish(self.client, records, &err, self.dead_letter)
+                .await;
+        }
+        if let Some(dead_letter) = self.dead_letter {
+            return dead_letter.send(self.client, records, &err, attempts).await;
+        }
+        Err(err)
+    }
+}
+
+struct PerRecord<'a, H> {
+    handler: H,
+    failure: Failure<'a>,
+}
+
+#[async_trait]
+impl<H: RecordHandler> Process for PerRecord<'_, H> {
+    async fn process(&self, records: &[ConsumeResponse], tracker: &mut OffsetTracker) -> Result<()> {
+        for record in records {
+            let handled = match record.check() {
+                Ok(()) => attempt(self.failure.dead_letter, || self.handler.handle(record)).await,
+                Err(err) => Err((err, 1)),
+            };
+            if let Err((err, attempts)) = handled {
+                self.failure
+                    .recover(std::slice::from_ref(record), err, attempts)
+                    .await?;
+            }
+            tracker.processed(record);
+        }
+        Ok(())
+    }
+}
+
+struct PerBatch<'a, H> {
+    handler: H,
+    failure: Failure<'a>,
+}
+
+#[async_trait]
+impl<H: BatchHandler> Process for PerBatch<'_, H> {
+    async fn process(&self, records: &[ConsumeResponse], tracker: &mut OffsetTracker) -> Result<()> {
+        let mut valid = Cow::Borrowed(records);
+        if records.iter().any(|record| record.invalid.is_some()) {
+            for record in records {
+                if let Err(err) = record.check() {
+                    self.failure.recover(std::slice::from_ref(record), err, 1).await?;
+                }
+            }
+            valid = Cow::Owned(records.iter().filter(|r| r.invalid.is_none()).cloned().collect());
+        }
+        if !valid.is_empty() {
+            let handled = attempt(self.failure.dead_letter, || self.handler.handle_batch(&valid)).await;
+            if let Err((err, attempts)) = handled {
+                self.failure.recover(&valid, err, attempts).await?;
+            }
+        }
+        records.iter().for_each(|record| tracker.processed(record));
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use apache_avro::schema_compatibility::SchemaCompatibility;
+use serde_json::Value;
+
+use crate::error::{Error, ErrorKind, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaFormat {
+    JsonSchema,
+    Avro,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    None,
+    Backward,
+    Forward,
+    Full,
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaVersion {
+    pub topic: String,
+    pub version: u32,
+    pub format: SchemaFormat,
+    pub definition: String,
+    compiled: Arc<CompiledSchema>,
+}
+
+#[derive(Debug)]
+enum CompiledSchema {
+    Json(Value, jsonschema::Validator),
+    Avro(apache_avro::Schema),
+}
+
+impl SchemaVersion {
+    pub fn validate(&self, value: &str) -> Result<()> {
+        let instance: Value = serde_json::from_str(value).map_err(|err| {
+            Error::new(
+                format!("{} v{}: payload is not valid json: {}", self.topic, self.version, err),
+                ErrorKind::InvalidData,
+            )
+        })?;
+        let result = match self.compiled.as_ref() {
+            CompiledSchema::Json(_, validator) => validator.validate(&instance).map_err(|err| err.to_string()),
+            CompiledSchema::Avro(schema) => apache_avro::types::Value::try_from(instance)
+                .and_then(|datum| datum.resolve(schema))
+                .map(|_| ())
+                .map_err(|err| err.to_string()),
+        };
+        result.map_err(|err| {
+            Error::new(
+                format!("{} v{}: schema validation failed: {}", self.topic, self.version, err),
+                ErrorKind::InvalidData,
+            )
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Subject {
+    compatibility: Option<Compatibility>,
+    versions: Vec<SchemaVersion>,
+}
+
+#[derive(Debug)]
+pub struct SchemaRegistry {
+    compatibility: Compatibility,
+    subjects: RwLock<HashMap<String, Subject>>,
+}
+
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        Self::new(Compatibility::Backward)
+    }
+}
+
+impl SchemaRegistry {
+    pub fn new(compatibility: Compatibility) -> Self {
+        Self {
+            compatibility,
+            subjects: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn set_compatibility(&self, topic: impl Into<String>, compatibility: Compatibility) {
+        let mut subjects = self.subjects.write().unwrap();
+        subjects.entry(topic.into()).or_default().compatibility = Some(compatibility);
+    }
+
+    pub fn compatibility(&self, topic: &str) -> Compatibility {
+        let subjects = self.subjects.read().unwrap();
+        subjects
+            .get(topic)
+            .and_then(|subject| subject.compatibility)
+            .unwrap_or(self.compatibility)
+    }
+
+    /// Registers a new schema version for the topic, returning its version number.
+    /// Registering the latest definition again is a no-op.
+    pub fn register(
+        &self,
+        topic: impl Into<String>,
+        format: SchemaFormat,
+        definition: impl Into<String>,
+    ) -> Result<u32> {
+        let topic = topic.into();
+        let definition = definition.into();
+        let compiled = compile(format, &definition)?;
+
+        let mut subjects = self.subjects.write().unwrap();
+        let subject = subjects.entry(topic.clone()).or_default();
+        if let Some(latest) = subject.versions.last() {
+            if latest.definition == definition {
+                return Ok(latest.version);
+            }
+            let compatibility = subject.compatibility.unwrap_or(self.compatibility);
+            check(&latest.compiled, &compiled, compatibility).map_err(|err| {
+                Error::new(
+                    format!("{}: incompatible schema: {}", topic, err),
+                    ErrorKind::InvalidData,
+                )
+            })?;
+        }
+
+        let version = subject.versions.len() as u32 + 1;
+        subject.versions.push(SchemaVersion {
+            topic,
+            version,
+            format,
+            definition,
+            compiled: Arc::new(compiled),
+        });
+        Ok(version)
+    }
+
+    pub fn latest(&self, topic: &str) -> Option<SchemaVersion> {
+        let subjects = self.subjects.read().unwrap();
+        subjects.get(topic).and_then(|subject| subject.versions.last().cloned())
+    }
+
+    pub fn version(&self, topic: &str, version: u32) -> Option<SchemaVersion> {
+        let subjects = self.subjects.read().unwrap();
+        subjects
+            .get(topic)
+            .and_then(|subject| subject.versions.iter().find(|v| v.version == version).cloned())
+    }
+
+    pub fn versions(&self, topic: &str) -> Vec<u32> {
+        let subjects = self.subjects.read().unwrap();
+        subjects
+            .get(topic)
+            .map(|subject| subject.versions.iter().map(|v| v.version).collect())
+            .unwrap_or_default()
+    }
+
+    /// Checks a candidate schema against the latest registered version without registering it.
+    pub fn check_compatibility(&self, topic: &str, format: SchemaFormat, definition: &str) -> Result<()> {
+        let candidate = compile(format, definition)?;
+        match self.latest(topic) {
+            Some(latest) => check(&latest.compiled, &candidate, self.compatibility(topic)).map_err(|err| {
+                Error::new(
+                    format!("{}: incompatible schema: {}", topic, err),
+                    ErrorKind::InvalidData,
+                )
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Validates a payload against the latest schema of the topic; topics without a schema always pass.
+    pub fn validate(&self, topic: &str, value: &str) -> Result<()> {
+        match self.latest(topic) {
+            Some(latest) => latest.validate(value),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Offline compatibility check between two schema definitions of the same format.
+pub fn check_compatibility(
+    format: SchemaFormat,
+    previous: &str,
+    next: &str,
+    compatibility: Compatibility,
+) -> Result<()> {
+    let previous = compile(format, previous)?;
+    let next = compile(format, next)?;
+    check(&previous, &next, compatibility)
+        .map_err(|err| Error::new(format!("incompatible schema: {}", err), ErrorKind::InvalidData))
+}
+
+fn compile(format: SchemaFormat, definition: &str) -> Result<CompiledSchema> {
+    match format {
+        SchemaFormat::JsonSchema => {
+            let schema: Value = serde_json::from_str(definition)
+                .map_err(|err| Error::new(format!("invalid json schema: {}", err), ErrorKind::InvalidData))?;
+            let validator = jsonschema::validator_for(&schema)
+                .map_err(|err| Error::new(format!("invalid json schema: {}", err), ErrorKind::InvalidData))?;
+            Ok(CompiledSchema::Json(schema, validator))
+        }
+        SchemaFormat::Avro => apache_avro::Schema::parse_str(definition)
+            .map(CompiledSchema::Avro)
+            .map_err(|err| Error::new(format!("invalid avro schema: {}", err), ErrorKind::InvalidData)),
+    }
+}
+
+// Backward: consumers on `next` can read data written with `previous`.
+// Forward: consumers on `previous` can read data written with `next`.
+fn check(
+    previous: &CompiledSchema,
+    next: &CompiledSchema,
+    compatibility: Compatibility,
+) -> std::result::Result<(), String> {
+    let (backward, forward) = match compatibility {
+        Compatibility::None => return Ok(()),
+        Compatibility::Backward => (true, false),
+        Compatibility::Forward => (false, true),
+        Compatibility::Full => (true, true),
+    };
+    if backward {
+        can_read(previous, next).map_err(|err| format!("backward: {}", err))?;
+    }
+    if forward {
+        can_read(next, previous).map_err(|err| format!("forward: {}", err))?;
+    }
+    Ok(())
+}
+
+fn can_read(writer: &CompiledSchema, reader: &CompiledSchema) -> std::result::Result<(), String> {
+    match (writer, reader) {
+        (CompiledSchema::Avro(writer), CompiledSchema::Avro(reader)) => SchemaCompatibility::can_read(writer, reader)
+            .map(|_| ())
+            .map_err(|err| err.to_string()),
+        (CompiledSchema::Json(writer, _), CompiledSchema::Json(reader, _)) => json_can_read(writer, reader, "$"),
+        _ => Err("schema format changed".to_string()),
+    }
+}
+
+fn json_can_read(writer: &Value, reader: &Value, path: &str) -> std::result::Result<(), String> {
+    let writer_types = json_types(writer);
+    let reader_types = json_types(reader);
+    if !reader_types.is_empty() {
+        let accepted = |t: &&str| reader_types.contains(t) || (*t == "integer" && reader_types.contains(&"number"));
+        if let Some(missing) = writer_types.iter().find(|t| !accepted(t)) {
+            return Err(format!("{}: type {} is not accepted by the reader", path, missing));
+        }
+    }
+
+    if let Some(reader_enum) = reader.get("enum").and_then(Value::as_array) {
+        let writer_enum = writer.get("enum").and_then(Value::as_array);
+        match writer_enum {
+            Some(values) => {
+                if let Some(value) = values.iter().find(|v| !reader_enum.contains(v)) {
+                    return Err(format!("{}: enum value {} was removed", path, value));
+                }
+            }
+            None => return Err(format!("{}: enum constraint was added", path)),
+        }
+    }
+
+    let writer_props = writer.get("properties").and_then(Value::as_object);
+    let reader_props = reader.get("properties").and_then(Value::as_object);
+    let writer_required = json_required(writer);
+    for field in json_required(reader) {
+        let has_default = reader_props
+            .and_then(|props| props.get(field))
+            .map(|prop| prop.get("default").is_some())
+            .unwrap_or(false);
+        if !writer_required.contains(&field) && !has_default {
+            return Err(format!("{}: required field {} may be missing", path, field));
+        }
+    }
+    if reader.get("additionalProperties") == Some(&Value::Bool(false)) {
+        if let Some(props) = writer_props {
+            if let Some(field) = props
+                .keys()
+                .find(|k| !reader_props.map(|r| r.contains_key(*k)).unwrap_or(false))
+            {
+                return Err(format!("{}: field {} is not allowed by the reader", path, field));
+            }
+        }
+    }
+    if let (Some(writer_props), Some(reader_props)) = (writer_props, reader_props) {
+        for (field, reader_prop) in reader_props {
+            if let Some(writer_prop) = writer_props.get(field) {
+                json_can_read(writer_prop, reader_prop, &format!("{}.{}", path, field))?;
+            }
+        }
+    }
+
+    if let (Some(writer_items), Some(reader_items)) = (writer.get("items"), reader.get("items")) {
+        json_can_read(writer_items, reader_items, &format!("{}[]", path))?;
+    }
+    Ok(())
+}
+
+fn json_types(schema: &Value) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn json_required(schema: &Value) -> Vec<&str> {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|fields| fields.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
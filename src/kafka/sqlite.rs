@@ -0,0 +1,131 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, Transaction};
+
+use super::{Header, Message, OutboxEntry, OutboxStore, ProduceResponse};
+use crate::error::{Error, ErrorKind, Result};
+
+pub const OUTBOX_TABLE: &str = "upstash_outbox";
+
+fn sqlite_error(err: rusqlite::Error) -> Error {
+    Error::new(format!("sqlite outbox failed: {}", err), ErrorKind::Internal)
+}
+
+/// Outbox kept in the `upstash_outbox` table of a SQLite database.
+pub struct SqliteOutbox {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteOutbox {
+    /// Creates the outbox table if it does not exist yet.
+    pub fn new(conn: Connection) -> Result<Self> {
+        Self::create_table(&conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    pub fn create_table(conn: &Connection) -> Result<()> {
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {OUTBOX_TABLE} (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                topic TEXT NOT NULL,
+                partition INTEGER NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                headers TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                sent_partition INTEGER,
+                sent_offset INTEGER
+            )"
+        ))
+        .map_err(sqlite_error)
+    }
+
+    /// Adds a message to the outbox; call it inside the transaction writing the business data.
+    pub fn enqueue(conn: &Connection, message: &Message) -> Result<u64> {
+        let headers = serde_json::to_string(&message.headers)
+            .map_err(|err| Error::new(format!("invalid headers: {}", err), ErrorKind::InvalidData))?;
+        conn.execute(
+            &format!("INSERT INTO {OUTBOX_TABLE} (topic, partition, key, value, headers) VALUES (?1, ?2, ?3, ?4, ?5)"),
+            params![
+                message.topic,
+                message.partition as i64,
+                message.key,
+                message.value,
+                headers
+            ],
+        )
+        .map_err(sqlite_error)?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    /// Runs `f` in a transaction on the outbox connection, committing when it succeeds.
+    pub fn transaction<T>(&self, f: impl FnOnce(&Transaction) -> Result<T>) -> Result<T> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(sqlite_error)?;
+        let value = f(&tx)?;
+        tx.commit().map_err(sqlite_error)?;
+        Ok(value)
+    }
+
+    fn row(row: &rusqlite::Row) -> rusqlite::Result<(OutboxEntry, String)> {
+        let partition: i64 = row.get(2)?;
+        let entry = OutboxEntry {
+            id: row.get::<_, i64>(0)? as u64,
+            message: Message {
+                topic: row.get(1)?,
+                partition: partition as u64,
+                key: row.get(3)?,
+                value: row.get(4)?,
+                headers: Vec::new(),
+            },
+            attempts: row.get(6)?,
+        };
+        Ok((entry, row.get(5)?))
+    }
+}
+
+#[async_trait]
+impl OutboxStore for SqliteOutbox {
+    async fn pending(&self, limit: usize, max_attempts: u32) -> Result<Vec<OutboxEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn
+            .prepare(&format!(
+                "SELECT id, topic, partition, key, value, headers, attempts FROM {OUTBOX_TABLE}
+                WHERE sent_offset IS NULL AND attempts < ?1 ORDER BY id LIMIT ?2"
+            ))
+            .map_err(sqlite_error)?;
+        let rows = statement
+            .query_map(params![max_attempts, limit as i64], Self::row)
+            .map_err(sqlite_error)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let (mut entry, headers) = row.map_err(sqlite_error)?;
+            entry.message.headers = serde_json::from_str::<Vec<Header>>(&headers)
+                .map_err(|err| Error::new(format!("invalid headers: {}", err), ErrorKind::InvalidData))?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    async fn mark_sent(&self, id: u64, response: &ProduceResponse) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            &format!("UPDATE {OUTBOX_TABLE} SET sent_partition = ?1, sent_offset = ?2 WHERE id = ?3"),
+            params![response.partition as i64, response.offset as i64, id as i64],
+        )
+        .map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: u64, err: &Error) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            &format!("UPDATE {OUTBOX_TABLE} SET attempts = attempts + 1, last_error = ?1 WHERE id = ?2"),
+            params![err.to_string(), id as i64],
+        )
+        .map_err(sqlite_error)?;
+        Ok(())
+    }
+}
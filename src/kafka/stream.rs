@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream};
+use futures::Stream;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+use super::{ConsumeOptions, ConsumeRequest, ConsumeResponse, KafkaService};
+use crate::error::Result;
+use crate::Client;
+
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    pub options: ConsumeOptions,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_retries: u32,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            options: ConsumeOptions::default(),
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_retries: 5,
+        }
+    }
+}
+
+impl StreamConfig {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.min_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<watch::Sender<bool>>);
+
+impl StopHandle {
+    pub(crate) fn new() -> (Self, watch::Receiver<bool>) {
+        let (sender, receiver) = watch::channel(false);
+        (Self(Arc::new(sender)), receiver)
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.0.subscribe()
+    }
+
+    pub fn stop(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+struct State {
+    client: Client,
+    group: String,
+    consumer: String,
+    req: ConsumeRequest,
+    config: StreamConfig,
+    buffer: VecDeque<ConsumeResponse>,
+    empty_polls: u32,
+    failures: u32,
+    done: bool,
+    stop: watch::Receiver<bool>,
+    // keeps the channel open while handles are dropped
+    _stop: StopHandle,
+}
+
+pub(crate) async fn wait_or_stop(stop: &mut watch::Receiver<bool>, duration: Duration) {
+    if *stop.borrow() {
+        return;
+    }
+    tokio::select! {
+        _ = sleep(duration) => {}
+        _ = stop.changed() => {}
+    }
+}
+
+impl State {
+    async fn next(mut self) -> Option<(Result<ConsumeResponse>, Self)> {
+        loop {
+            if let Some(record) = self.buffer.pop_front() {
+                return Some((Ok(record), self));
+            }
+            if self.done || *self.stop.borrow() {
+                return None;
+            }
+            let result = self
+                .client
+                .consumer()
+                .consume(
+                    &self.group,
+                    &self.consumer,
+                    self.req.clone(),
+                    self.config.options.clone(),
+                )
+                .await;
+            match result {
+                Ok(records) if records.is_empty() => {
+                    self.failures = 0;
+                    self.empty_polls += 1;
+                    let backoff = self.config.backoff(self.empty_polls);
+                    wait_or_stop(&mut self.stop, backoff).await;
+                }
+                Ok(records) => {
+                    self.failures = 0;
+                    self.empty_polls = 0;
+                    self.buffer.extend(records);
+                }
+                Err(err) => {
+                    self.failures += 1;
+                    if self.failures > self.config.max_retries {
+                        self.done = true;
+                        return Some((Err(err), self));
+                    }
+                    let backoff = self.config.backoff(self.failures);
+                    wait_or_stop(&mut self.stop, backoff).await;
+                }
+            }
+        }
+    }
+}
+
+/// Polls a consumer instance until stopped, yielding records one by one.
+/// The stream ends with an error once `max_retries` consecutive polls have failed.
+pub struct ConsumerStream {
+    inner: BoxStream<'static, Result<ConsumeResponse>>,
+    stop: StopHandle,
+}
+
+impl ConsumerStream {
+    pub fn new(
+        client: Client,
+        group: impl Into<String>,
+        consumer: impl Into<String>,
+        req: ConsumeRequest,
+        config: StreamConfig,
+    ) -> Self {
+        let (stop, receiver) = StopHandle::new();
+        let state = State {
+            client,
+            group: group.into(),
+            consumer: consumer.into(),
+            req,
+            config,
+            buffer: VecDeque::new(),
+            empty_polls: 0,
+            failures: 0,
+            done: false,
+            stop: receiver,
+            _stop: stop.clone(),
+        };
+        Self {
+            inner: Box::pin(stream::unfold(state, State::next)),
+            stop,
+        }
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+}
+
+impl Stream for ConsumerStream {
+    type Item = Result<ConsumeResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
@@ -0,0 +1,203 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{BatchHandler, ConsumeResponse};
+use crate::error::{Error, ErrorKind, Result};
+
+pub const SIGNATURE_HEADER: &str = "Upstash-Signature";
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    iss: String,
+    #[serde(default)]
+    sub: String,
+    exp: u64,
+    nbf: u64,
+    body: String,
+}
+
+fn unauthorized(msg: impl Into<String>) -> Error {
+    Error::new(msg, ErrorKind::ApiError("unauthorized".to_string()))
+}
+
+/// Checks the signature Upstash sends with every webhook request, a JWT signed with HS256 using the
+/// current signing key, or the next one while keys are being rotated.
+#[derive(Debug, Clone)]
+pub struct WebhookVerifier {
+    current_key: String,
+    next_key: String,
+    pub clock_skew: Duration,
+}
+
+impl WebhookVerifier {
+    pub fn new(current_key: impl Into<String>, next_key: impl Into<String>) -> Self {
+        Self {
+            current_key: current_key.into(),
+            next_key: next_key.into(),
+            clock_skew: Duration::from_secs(5),
+        }
+    }
+
+    /// Verifies the signature against the raw body, and against the receiving url when given.
+    pub fn verify(&self, signature: &str, body: &[u8], url: Option<&str>) -> Result<()> {
+        self.verify_with(&self.current_key, signature, body, url)
+            .or_else(|_| self.verify_with(&self.next_key, signature, body, url))
+    }
+
+    fn verify_with(&self, key: &str, signature: &str, body: &[u8], url: Option<&str>) -> Result<()> {
+        let mut parts = signature.split('.');
+        let (header, payload, signed) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(payload), Some(signed), None) => (header, payload, signed),
+            _ => return Err(unauthorized("malformed webhook signature")),
+        };
+        let signed = URL_SAFE_NO_PAD
+            .decode(signed.trim_end_matches('='))
+            .map_err(|_| unauthorized("malformed webhook signature"))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+            .map_err(|_| Error::new("invalid webhook signing key", ErrorKind::Internal))?;
+        mac.update(format!("{}.{}", header, payload).as_bytes());
+        mac.verify_slice(&signed)
+            .map_err(|_| unauthorized("invalid webhook signature"))?;
+
+        let claims = URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .ok()
+            .and_then(|payload| serde_json::from_slice::<Claims>(&payload).ok())
+            .ok_or_else(|| unauthorized("malformed webhook signature claims"))?;
+        if claims.iss != "Upstash" {
+            return Err(unauthorized(format!("invalid webhook issuer {}", claims.iss)));
+        }
+        if url.is_some_and(|url| url != claims.sub) {
+            return Err(unauthorized(format!("webhook signed for {}", claims.sub)));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let skew = self.clock_skew.as_secs();
+        if now > claims.exp + skew || now + skew < claims.nbf {
+            return Err(unauthorized("webhook signature expired"));
+        }
+        let hash = URL_SAFE_NO_PAD.encode(Sha256::digest(body));
+        if hash != claims.body.trim_end_matches('=') {
+            return Err(unauthorized("webhook body does not match its signature"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl WebhookResponse {
+    fn new(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: body.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Payload {
+    Batch(Vec<ConsumeResponse>),
+    Record(ConsumeResponse),
+}
+
+/// Receives the records Upstash pushes to a webhook and passes them to the handler.
+/// Independent of any web framework: hand it the signature header and the raw body.
+pub struct WebhookReceiver<H> {
+    handler: H,
+    verifier: Option<WebhookVerifier>,
+    url: Option<String>,
+}
+
+impl<H: BatchHandler> WebhookReceiver<H> {
+    pub fn new(handler: H, verifier: WebhookVerifier) -> Self {
+        Self {
+            handler,
+            verifier: Some(verifier),
+            url: None,
+        }
+    }
+
+    /// Accepts every payload without checking its signature, anyone who can reach the webhook can send records.
+    pub fn insecure_without_verification(handler: H) -> Self {
+        Self {
+            handler,
+            verifier: None,
+            url: None,
+        }
+    }
+
+    /// Public url of the webhook, checked against the url the request was signed for.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Accepts a single record or an array of records.
+    pub fn parse(body: &[u8]) -> Result<Vec<ConsumeResponse>> {
+        let payload = serde_json::from_slice(body)
+            .map_err(|err| Error::new(format!("invalid webhook payload: {}", err), ErrorKind::InvalidData))?;
+        Ok(match payload {
+            Payload::Batch(records) => records,
+            Payload::Record(record) => vec![record],
+        })
+    }
+
+    pub async fn receive(&self, signature: Option<&str>, body: &[u8]) -> WebhookResponse {
+        if let Some(verifier) = &self.verifier {
+            let signature = match signature {
+                Some(signature) => signature,
+                None => return WebhookResponse::new(401, "missing webhook signature"),
+            };
+            if let Err(err) = verifier.verify(signature, body, self.url.as_deref()) {
+                return WebhookResponse::new(401, err.to_string());
+            }
+        }
+        let records = match Self::parse(body) {
+            Ok(records) => records,
+            Err(err) => return WebhookResponse::new(400, err.to_string()),
+        };
+        match self.handler.handle_batch(&records).await {
+            Ok(()) => WebhookResponse::new(200, "ok"),
+            Err(err) => WebhookResponse::new(500, err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "axum")]
+impl<H: BatchHandler + 'static> WebhookReceiver<H> {
+    /// Serves the receiver on POST `path`.
+    pub fn router(self, path: &str) -> axum::Router {
+        use axum::body::Bytes;
+        use axum::http::{HeaderMap, StatusCode};
+        use axum::routing::post;
+        use std::sync::Arc;
+
+        let receiver = Arc::new(self);
+        axum::Router::new().route(
+            path,
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                let signature = headers.get(SIGNATURE_HEADER).and_then(|v| v.to_str().ok());
+                let response = receiver.receive(signature, &body).await;
+                let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                (status, response.body)
+            }),
+        )
+    }
+}
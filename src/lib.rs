@@ -2,6 +2,8 @@ use error::{Context, Error, ErrorKind, Result};
 use reqwest::header::HeaderMap;
 
 use once_cell::sync::OnceCell;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub mod error;
 mod kafka;
@@ -31,6 +33,11 @@ impl<'client> Handler<'client> {
 pub struct Client {
     inner: reqwest::Client,
     base_url: url::Url,
+    name_cache: Option<Arc<NameCache>>,
+    #[cfg(feature = "schema")]
+    schema_registry: Option<Arc<SchemaRegistry>>,
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
 }
 
 impl Client {
@@ -41,19 +48,71 @@ impl Client {
         let mut base_url = url::Url::parse("https://api.upstash.com/v2").unwrap();
         base_url.set_username(&email).unwrap();
         base_url.set_password(Some(&api_key)).unwrap();
-        Self { inner, base_url }
+        Self {
+            inner,
+            base_url,
+            name_cache: None,
+            #[cfg(feature = "schema")]
+            schema_registry: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
     }
 
     pub fn kafka_env() -> Self {
-        let inner = reqwest::Client::new();
         let username = std::env::var("KAFKA_USERNAME").expect("KAFKA_USERNAME not set");
         let password = std::env::var("KAFKA_PASSWORD").expect("KAFKA_PASSWORD not set");
         let rest_server = std::env::var("KAFKA_REST_SERVER").expect("KAFKA_REST_SERVER not set");
-        let rest_server = format!("https://{}", &rest_server);
+        Self::kafka_from(&rest_server, &username, &password)
+    }
+
+    pub fn kafka_from(rest_server: &str, username: &str, password: &str) -> Self {
+        let inner = reqwest::Client::new();
+        let rest_server = format!("https://{}", rest_server);
         let mut base_url = url::Url::parse(&rest_server).unwrap();
-        base_url.set_username(&username).unwrap();
-        base_url.set_password(Some(&password)).unwrap();
-        Self { inner, base_url }
+        base_url.set_username(username).unwrap();
+        base_url.set_password(Some(password)).unwrap();
+        Self {
+            inner,
+            base_url,
+            name_cache: None,
+            #[cfg(feature = "schema")]
+            schema_registry: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
+
+    /// Caches the cluster and topic lists behind the name lookups for `ttl`.
+    pub fn with_name_cache(mut self, ttl: Duration) -> Self {
+        self.name_cache = Some(Arc::new(NameCache::new(ttl)));
+        self
+    }
+
+    pub fn name_cache(&self) -> Option<&NameCache> {
+        self.name_cache.as_deref()
+    }
+
+    #[cfg(feature = "schema")]
+    pub fn with_schema_registry(mut self, registry: SchemaRegistry) -> Self {
+        self.schema_registry = Some(Arc::new(registry));
+        self
+    }
+
+    #[cfg(feature = "schema")]
+    pub fn schema_registry(&self) -> Option<&SchemaRegistry> {
+        self.schema_registry.as_deref()
+    }
+
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    #[cfg(feature = "compression")]
+    pub fn compression(&self) -> Option<&Compression> {
+        self.compression.as_ref()
     }
 
     pub fn initialize(self) {
@@ -230,6 +289,89 @@ mod tests {
         println!("Upstash List Clusters:\n{:#?}", &list);
     }
 
+    #[test]
+    fn cluster_enums() {
+        // cargo test -p upstash --lib -- tests::cluster_enums --exact --nocapture
+
+        let cluster: ClusterResponse = serde_json::from_value(serde_json::json!({
+            "cluster_id": "1b729d79-0ac1-49cc-8226-ce55d5641e6a",
+            "name": "implrust",
+            "region": "ap-southeast-1",
+            "type": "moonshot",
+            "multizone": true,
+            "tcp_endpoint": "glowing-crab-5802-eu1-kafka.upstash.io",
+            "rest_endpoint": "glowing-crab-5802-eu1-rest-kafka.upstash.io",
+            "state": "active",
+            "username": "user",
+            "password": "password",
+            "max_retention_size": 1073741824,
+            "max_retention_time": 604800000,
+            "max_messages_per_second": 1000,
+            "creation_time": 1657000000,
+            "max_message_size": 1048576,
+            "max_partitions": 10
+        }))
+        .unwrap();
+        assert_eq!(cluster.region, Region::ApSoutheast1);
+        assert_eq!(cluster.type_name, ClusterType::Unknown("moonshot".to_string()));
+        assert_eq!(cluster.state, ResourceState::Active);
+
+        let req = CreateClusterRequest {
+            name: "demo".to_string(),
+            region: Region::Unknown("mars-north-1".to_string()),
+            multizone: false,
+        };
+        assert_eq!(serde_json::to_value(&req).unwrap()["region"], "mars-north-1");
+        assert_eq!(Region::from("eu-west-1"), CreateClusterRegion::EuWest1);
+    }
+
+    #[tokio::test]
+    async fn wait_until_active_polling() {
+        // cargo test -p upstash --lib -- tests::wait_until_active_polling --exact --nocapture
+
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let config = WaitConfig {
+            timeout: std::time::Duration::from_millis(300),
+            min_backoff: std::time::Duration::from_millis(10),
+            max_backoff: std::time::Duration::from_millis(20),
+        };
+        let polls = AtomicUsize::new(0);
+        let states = [
+            None,
+            Some(ResourceState::Creating),
+            Some(ResourceState::Pending),
+            Some(ResourceState::Active),
+        ];
+        let polled = wait_until_active("cluster one", config.clone(), || async {
+            let poll = polls.fetch_add(1, Ordering::SeqCst);
+            match &states[poll] {
+                Some(state) => Ok((state.clone(), poll)),
+                None => Err(Error::new("not found", ErrorKind::ApiError("404".to_string()))),
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(polled, 3);
+
+        let err = wait_until_active("cluster one", config.clone(), || async {
+            Ok((ResourceState::Failed, ()))
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            error::ErrorKind::InvalidState(ResourceState::Failed)
+        ));
+
+        let started = std::time::Instant::now();
+        let err = wait_until_active("cluster one", config, || async { Ok((ResourceState::Creating, ())) })
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::Timeout));
+        assert!(started.elapsed() >= std::time::Duration::from_millis(300));
+    }
+
     #[tokio::test]
     async fn get_cluster() {
         // cargo test -p upstash --lib -- tests::get_cluster --exact --nocapture
@@ -318,6 +460,182 @@ mod tests {
         println!("Upstash Create Topic:\n{:#?}", &topic);
     }
 
+    #[tokio::test]
+    async fn ensure_topic() {
+        // cargo test -p upstash --lib -- tests::ensure_topic --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::upstash_env().initialize();
+        let client = Client::upstash_instance().unwrap();
+
+        let req = CreateTopicRequest {
+            name: "one".to_string(),
+            partitions: 1,
+            retention_time: 3600000,
+            retention_size: 1048576,
+            max_message_size: 102400,
+            cleanup_policy: CreateTopicCleanupPolicy::Compact,
+            cluster_id: "1b729d79-0ac1-49cc-8226-ce55d5641e6a".to_string(),
+        };
+        client.kafka().ensure_topic(req.clone()).await.unwrap();
+        let ensured = client.kafka().ensure_topic(req).await.unwrap();
+        assert_eq!(ensured.outcome, EnsureOutcome::Unchanged);
+        println!("Upstash Ensure Topic:\n{:#?}", &ensured);
+    }
+
+    #[cfg(feature = "manifest")]
+    #[tokio::test]
+    async fn manifest_plan() {
+        // cargo test -p upstash --features manifest --lib -- tests::manifest_plan --exact --nocapture
+
+        let manifest = Manifest::from_yaml(
+            r#"
+clusters:
+  - name: demo
+    region: eu-west-1
+    topics:
+      - name: orders
+        partitions: 1
+        retention_time: 7200000
+        retention_size: 1048576
+        max_message_size: 102400
+        cleanup_policy: delete
+      - name: payments
+        partitions: 2
+        retention_time: 3600000
+        retention_size: 1048576
+        max_message_size: 102400
+        cleanup_policy: compact
+    credentials:
+      - name: app
+        topic: orders
+        permissions: ALL
+  - name: staging
+    region: us-east-1
+"#,
+        )
+        .unwrap();
+        let toml = Manifest::from_toml(
+            r#"
+[[clusters]]
+name = "demo"
+region = "eu-west-1"
+"#,
+        )
+        .unwrap();
+        assert_eq!(toml.clusters[0].region, Region::EuWest1);
+        assert!(Manifest::from_toml(
+            "[[clusters]]\nname = \"a\"\nregion = \"eu-west-1\"\n[[clusters]]\nname = \"a\"\nregion = \"eu-west-1\""
+        )
+        .is_err());
+
+        let cluster = |id: &str, name: &str| -> ClusterResponse {
+            serde_json::from_value(serde_json::json!({
+                "cluster_id": id, "name": name, "region": "eu-west-1", "type": "free", "multizone": false,
+                "tcp_endpoint": "", "rest_endpoint": "", "state": "active", "username": "", "password": "",
+                "max_retention_size": 0, "max_retention_time": 0, "max_messages_per_second": 0,
+                "creation_time": 0, "max_message_size": 0, "max_partitions": 0
+            }))
+            .unwrap()
+        };
+        let topic = |id: &str, name: &str, retention_time: i32| -> TopicResponse {
+            serde_json::from_value(serde_json::json!({
+                "topic_id": id, "topic_name": name, "cluster_id": "c1", "region": "eu-west-1",
+                "creation_time": 0, "state": "active", "partitions": 1, "multizone": false,
+                "tcp_endpoint": "", "rest_endpoint": "", "username": "", "password": "",
+                "cleanup_policy": "delete", "retention_size": 1048576, "retention_time": retention_time,
+                "max_message_size": 102400
+            }))
+            .unwrap()
+        };
+        let live = LiveState {
+            clusters: vec![
+                cluster("c1", "demo"),
+                cluster("c2", "demo-legacy"),
+                cluster("c3", "prod"),
+            ],
+            topics: [(
+                "c1".to_string(),
+                vec![topic("t1", "orders", 3600000), topic("t2", "old", 3600000)],
+            )]
+            .into_iter()
+            .collect(),
+            credentials: Vec::new(),
+        };
+
+        let plan = Plan::new(&manifest, &live, false).unwrap();
+        assert_eq!(plan.deletes(), 0);
+        let lines: Vec<String> = plan.changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "~ topic demo/orders: retention time 3600000 -> 7200000",
+                "+ topic demo/payments (partitions 2, retention time 3600000, retention size 1048576, max message size 102400, compact)",
+                "+ credential demo/app (ALL on orders)",
+                "+ cluster staging (us-east-1)",
+            ]
+        );
+        assert!(plan
+            .to_string()
+            .ends_with("Plan: 3 to create, 1 to update, 0 to delete.\n"));
+
+        let plan = Plan::new(&manifest, &live, true).unwrap();
+        let deletes: Vec<String> = plan
+            .changes
+            .iter()
+            .filter(|c| c.is_delete())
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(deletes, vec!["- topic demo/old"]);
+
+        // only clusters named with the prefix are pruned
+        let mut prefixed = manifest.clone();
+        prefixed.cluster_prefix = Some("demo".to_string());
+        let plan = Plan::new(&prefixed, &live, true).unwrap();
+        let deletes: Vec<String> = plan
+            .changes
+            .iter()
+            .filter(|c| c.is_delete())
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(deletes, vec!["- topic demo/old", "- cluster demo-legacy"]);
+
+        let client = Client::kafka_from("127.0.0.1:9", "user", "password");
+        let err = client.kafka().apply_plan(&plan, false).await.unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+
+        let mut changed = live.clone();
+        changed.topics.get_mut("c1").unwrap()[0].partitions = 3;
+        assert!(Plan::new(&manifest, &changed, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn find_topic_by_name() {
+        // cargo test -p upstash --lib -- tests::find_topic_by_name --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        let client = Client::upstash_env().with_name_cache(std::time::Duration::from_secs(30));
+
+        let cluster = client.kafka().find_cluster_by_name("implrust").await.unwrap().unwrap();
+        let topic = client
+            .kafka()
+            .find_topic_by_name(&cluster.cluster_id, "one")
+            .await
+            .unwrap();
+        println!("Upstash Find Topic By Name:\n{:#?}", &topic);
+    }
+
+    #[test]
+    fn ambiguous_names() {
+        // cargo test -p upstash --lib -- tests::ambiguous_names --exact --nocapture
+
+        let found = unique("topic", "orders", vec!["t1", "t2"], |id| id).unwrap_err();
+        assert!(matches!(found.kind(), error::ErrorKind::InvalidData));
+        assert!(found.to_string().contains("t1, t2"));
+        assert_eq!(unique("topic", "orders", vec!["t1"], |id| id).unwrap(), Some("t1"));
+        assert_eq!(unique("topic", "orders", Vec::<&str>::new(), |id| id).unwrap(), None);
+    }
+
     #[tokio::test]
     async fn get_topic() {
         // cargo test -p upstash --lib -- tests::get_topic --exact --nocapture
@@ -484,6 +802,94 @@ mod tests {
         println!("Upstash Kafka Produce:\n{:#?}", &response);
     }
 
+    #[tokio::test]
+    async fn produce_report() {
+        // cargo test -p upstash --lib -- tests::produce_report --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let messages = vec![
+            Message::new("one", "21", Some(0), Some("key")),
+            Message::new("missing", "22", Some(0), Some("key")),
+        ];
+
+        let report = client.producer().produce_report(messages).await.unwrap();
+        println!("Upstash Kafka Produce Report:\n{:#?}", &report);
+        println!("Retry:\n{:#?}", report.into_failed());
+    }
+
+    #[test]
+    fn produce_report_pairing() {
+        // cargo test -p upstash --lib -- tests::produce_report_pairing --exact --nocapture
+
+        let messages = vec![
+            Message::new("one", "21", Some(0), Some("key")),
+            Message::new("one", "invalid", Some(0), Some("key")),
+            Message::new("missing", "22", Some(0), Some("key")),
+            Message::new("one", "23", Some(0), Some("key")),
+        ];
+        let rejected = vec![
+            None,
+            Some(error::Error::new("invalid value", error::ErrorKind::InvalidData)),
+            None,
+            None,
+        ];
+        let responses = serde_json::from_value(serde_json::json!([
+            { "topic": "one", "partition": 0, "offset": 108 },
+            { "error": "topic not found" }
+        ]))
+        .unwrap();
+
+        let report = ProduceReport::new(messages, rejected, responses);
+        assert!(!report.is_success());
+        let results: Vec<_> = report.results.iter().map(|r| (&r.message.value, &r.result)).collect();
+        assert!(matches!(results[0], (v, Ok(r)) if v == "21" && r.offset == 108));
+        assert!(
+            matches!(results[1], (v, Err(e)) if v == "invalid" && matches!(e.kind(), error::ErrorKind::InvalidData))
+        );
+        assert!(matches!(results[2], (v, Err(e)) if v == "22" && matches!(e.kind(), error::ErrorKind::ApiError(_))));
+        assert!(matches!(results[3], (v, Err(e)) if v == "23" && e.to_string() == "missing produce response"));
+        assert_eq!(report.into_failed().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn batching_producer() {
+        // cargo test -p upstash --lib -- tests::batching_producer --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let config = BatchConfig {
+            max_messages: 2,
+            ..Default::default()
+        };
+        let producer = BatchingProducer::new(client.clone(), config);
+        let deliveries: Vec<_> = (0..5)
+            .map(|i| producer.send(Message::new("one", i.to_string(), Some(0), Some("key"))))
+            .collect();
+        producer.shutdown().await;
+
+        for delivery in deliveries {
+            println!("Upstash Kafka Batch Produce:\n{:#?}", delivery.await);
+        }
+    }
+
+    #[test]
+    fn batching_producer_runtime() {
+        // cargo test -p upstash --lib -- tests::batching_producer_runtime --exact --nocapture
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client = Client::kafka_from("localhost", "user", "password");
+        let producer = BatchingProducer::new_in(client, BatchConfig::default(), runtime.handle());
+        runtime.block_on(producer.shutdown());
+    }
+
     #[tokio::test]
     async fn fetch() {
         // cargo test -p upstash --lib -- tests::fetch --exact --nocapture
@@ -492,16 +898,193 @@ mod tests {
         Client::kafka_env().initialize();
         let client = Client::kafka_instance().unwrap();
 
-        let req = FetchRequest {
-            topic: "one".to_string(),
-            partition: 0,
-            offset: 5,
-        };
+        let req = FetchRequest::new("one", 0, 5);
 
         let response = client.fetcher().fetch(req).await;
         println!("Upstash Kafka Fetch:\n{:#?}", &response);
     }
 
+    #[tokio::test]
+    async fn produce_fetch_get() {
+        // cargo test -p upstash --lib -- tests::produce_fetch_get --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let message = Message::new("one", "{\"path\": \"a/b?c=d&e\"}", None, Some("key #1"));
+        let response = client.producer().produce_get(message).await;
+        println!("Upstash Kafka Produce GET:\n{:#?}", &response);
+
+        let response = client.fetcher().fetch_get(FetchRequest::new("one", 0, 5)).await;
+        println!("Upstash Kafka Fetch GET:\n{:#?}", &response);
+    }
+
+    #[tokio::test]
+    async fn produce_get_guards() {
+        // cargo test -p upstash --lib -- tests::produce_get_guards --exact --nocapture
+
+        let client = Client::kafka_from("localhost", "user", "password");
+        let large = Message::new("one", "x".repeat(MAX_GET_URL_LENGTH), None, Some("key"));
+        let err = client.producer().produce_get(large).await.unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+
+        let with_header = Message::new("one", "21", None, Some("key")).with_header("source", "billing");
+        let err = client.producer().produce_get(with_header).await.unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+
+        let req = FetchRequest::partitions(vec![TopicPartitionOffset::new("one", 0, 5)]);
+        let err = client.fetcher().fetch_get(req).await.unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+    }
+
+    #[tokio::test]
+    async fn fetch_request_validate() {
+        // cargo test -p upstash --lib -- tests::fetch_request_validate --exact --nocapture
+
+        let client = Client::kafka_from("localhost", "user", "password");
+        let partitions = vec![TopicPartitionOffset::new("one", 0, 5)];
+        let mixed = [
+            FetchRequest {
+                topic: Some("one".to_string()),
+                ..FetchRequest::partitions(partitions.clone())
+            },
+            FetchRequest {
+                partition: Some(0),
+                ..FetchRequest::partitions(partitions.clone())
+            },
+            FetchRequest {
+                topic_partition_offsets: partitions,
+                ..FetchRequest::new("one", 0, 5)
+            },
+            FetchRequest {
+                offset: None,
+                ..FetchRequest::new("one", 0, 5)
+            },
+        ];
+        for req in mixed {
+            let err = client.fetcher().fetch(req).await.unwrap_err();
+            assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn url_length_ignores_credentials() {
+        // cargo test -p upstash --lib -- tests::url_length_ignores_credentials --exact --nocapture
+
+        let client = Client::kafka_from("localhost", "user", &"p".repeat(MAX_GET_URL_LENGTH));
+        let url = client.base_url.join("produce/one/value").unwrap();
+        assert!(check_url_length(&url).is_ok());
+
+        let path = format!("produce/one/{}", "v".repeat(MAX_GET_URL_LENGTH));
+        let url = Client::kafka_from("localhost", "user", "password")
+            .base_url
+            .join(&path)
+            .unwrap();
+        let err = check_url_length(&url).unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+    }
+
+    #[tokio::test]
+    async fn fetch_partitions() {
+        // cargo test -p upstash --lib -- tests::fetch_partitions --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let req = FetchRequest::partitions(vec![
+            TopicPartitionOffset::new("one", 0, 5),
+            TopicPartitionOffset::new("two", 0, 0),
+        ])
+        .with_timeout(std::time::Duration::from_secs(1));
+        let response = client.fetcher().fetch(req).await;
+        println!("Upstash Kafka Fetch Partitions:\n{:#?}", &response);
+
+        let response = client.fetcher().fetch_range("one", 0, 5, 50).await;
+        println!("Upstash Kafka Fetch Range:\n{:#?}", &response);
+    }
+
+    #[test]
+    fn archive_roundtrip() {
+        // cargo test -p upstash --lib -- tests::archive_roundtrip --exact --nocapture
+
+        let records = vec![
+            ArchiveRecord {
+                topic: "one".to_string(),
+                partition: 0,
+                offset: 5,
+                key: "key".to_string(),
+                value: "{\"amount\": 21}\nline".to_string(),
+                headers: vec![Header::new("source", "billing")],
+            },
+            ArchiveRecord {
+                topic: "one".to_string(),
+                partition: 1,
+                offset: 6,
+                key: String::new(),
+                value: "22".to_string(),
+                headers: vec![],
+            },
+        ];
+        for format in [ArchiveFormat::Ndjson, ArchiveFormat::Binary] {
+            let mut writer = ArchiveWriter::new(Vec::new(), format);
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+            let archive = writer.finish().unwrap();
+
+            let read = ArchiveReader::new(archive.as_slice(), format)
+                .collect::<error::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(read, records);
+        }
+
+        let empty = ArchiveWriter::new(Vec::new(), ArchiveFormat::Binary).finish().unwrap();
+        assert_eq!(ArchiveReader::new(empty.as_slice(), ArchiveFormat::Binary).count(), 0);
+        assert_eq!(ArchiveReader::new(&b""[..], ArchiveFormat::Binary).count(), 0);
+        assert_eq!(ArchiveReader::new(&b""[..], ArchiveFormat::Ndjson).count(), 0);
+        assert!(ArchiveReader::new(&b"nope!"[..], ArchiveFormat::Binary)
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn export_import_topic() {
+        // cargo test -p upstash --lib -- tests::export_import_topic --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let mut writer = ArchiveWriter::new(Vec::new(), ArchiveFormat::Ndjson);
+        let range = ExportRange::new("one", [0]);
+        let exported = client.export_topic(&range, &mut writer).await;
+        println!("Upstash Kafka Export:\n{:#?}", &exported);
+
+        let archive = writer.finish().unwrap();
+        let reader = ArchiveReader::new(archive.as_slice(), ArchiveFormat::Ndjson);
+        let imported = client.import_topic("two", reader, 100).await;
+        println!("Upstash Kafka Import:\n{:#?}", &imported);
+    }
+
+    #[test]
+    fn fetch_request_body() {
+        // cargo test -p upstash --lib -- tests::fetch_request_body --exact --nocapture
+
+        let req = FetchRequest::partitions(vec![TopicPartitionOffset::new("one", 300, 5)]);
+        let body = serde_json::to_value(&req).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"topicPartitionOffsets": [{"topic": "one", "partition": 300, "offset": 5}]})
+        );
+        assert_eq!(
+            serde_json::to_value(FetchRequest::new("one", 0, 5)).unwrap(),
+            serde_json::json!({"topic": "one", "partition": 0, "offset": 5})
+        );
+    }
+
     #[tokio::test]
     async fn consume() {
         // cargo test -p upstash --lib -- tests::consume --exact --nocapture
@@ -510,16 +1093,75 @@ mod tests {
         Client::kafka_env().initialize();
         let client = Client::kafka_instance().unwrap();
 
-        let req = ConsumeRequest {
-            topic: "one".to_string(),
-        };
+        let req = ConsumeRequest::new("one");
         let group = "g1";
         let consumer = "c1";
 
-        let response = client.consumer().consume(group, consumer, req).await;
+        let options = ConsumeOptions {
+            auto_offset_reset: AutoOffsetReset::Earliest,
+            ..Default::default()
+        };
+
+        let response = client.consumer().consume(group, consumer, req, options).await;
         println!("Upstash Kafka Consume:\n{:#?}", &response);
     }
 
+    #[tokio::test]
+    async fn consume_topics() {
+        // cargo test -p upstash --lib -- tests::consume_topics --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let group = "g1";
+        let consumer = "c2";
+
+        let req = ConsumeRequest::topics(["one", "crab"]);
+        let records = client
+            .consumer()
+            .consume(group, consumer, req, ConsumeOptions::default())
+            .await
+            .unwrap();
+        for (topic, records) in ConsumeResponse::group_by_topic(records) {
+            println!("Upstash Kafka Consume {}:\n{:#?}", topic, &records);
+        }
+
+        let req = ConsumeRequest::pattern("o.*");
+        let response = client
+            .consumer()
+            .consume(group, consumer, req, ConsumeOptions::default())
+            .await;
+        println!("Upstash Kafka Consume Pattern:\n{:#?}", &response);
+    }
+
+    #[tokio::test]
+    async fn consumer_stream() {
+        // cargo test -p upstash --lib -- tests::consumer_stream --exact --nocapture
+
+        use futures::StreamExt;
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let stream = ConsumerStream::new(
+            client.clone(),
+            "g1",
+            "c1",
+            ConsumeRequest::new("one"),
+            StreamConfig::default(),
+        );
+        let stop = stream.stop_handle();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            stop.stop();
+        });
+
+        let records: Vec<_> = stream.collect().await;
+        println!("Upstash Kafka Consumer Stream:\n{:#?}", &records);
+    }
+
     #[tokio::test]
     async fn commit() {
         // cargo test -p upstash --lib -- tests::commit --exact --nocapture
@@ -528,11 +1170,18 @@ mod tests {
         Client::kafka_env().initialize();
         let client = Client::kafka_instance().unwrap();
 
-        let req = vec![
-            CommitRequest::new("one", 0, 106),
-            CommitRequest::new("one", 0, 107),
-            CommitRequest::new("one", 0, 108),
-        ];
+        let record = |offset: u64| ConsumeResponse {
+            key: "key".to_string(),
+            offset,
+            partition: 0,
+            timestamp: 0,
+            topic: "one".to_string(),
+            value: "21".to_string(),
+            headers: Vec::new(),
+            invalid: None,
+        };
+        // a single request for the partition, after the highest record
+        let req = CommitRequest::from_records(&[record(106), record(107), record(108)]);
         let group = "g1";
         let consumer = "c1";
 
@@ -540,6 +1189,795 @@ mod tests {
         println!("Upstash Kafka Commit:\n{:#?}", &response);
     }
 
+    #[tokio::test]
+    async fn committed() {
+        // cargo test -p upstash --lib -- tests::committed --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let req = vec![TopicPartition::new("one", 0)];
+        let response = client.handler("").committed("g1", "c1", req).await;
+        println!("Upstash Kafka Committed Offsets:\n{:#?}", &response);
+    }
+
+    #[test]
+    fn group_lag() {
+        // cargo test -p upstash --lib -- tests::group_lag --exact --nocapture
+
+        let committed = |partition: u64, offset: i64| CommittedOffset {
+            topic: "one".to_string(),
+            partition,
+            offset,
+            metadata: String::new(),
+        };
+        let offset = |partition: u64, offset: i64| PartitionOffset {
+            topic: "one".to_string(),
+            partition,
+            offset,
+        };
+        let lag = GroupLag::new(
+            "g1",
+            &[committed(0, 100), committed(1, -1)],
+            &[offset(0, 10), offset(1, 20)],
+            &[offset(0, 150), offset(1, 50)],
+        );
+
+        assert_eq!(lag.partitions[0].committed, Some(100));
+        assert_eq!(lag.partitions[0].lag, 50);
+        assert_eq!(lag.partitions[1].committed, None);
+        assert_eq!(lag.partitions[1].lag, 30);
+        assert_eq!(lag.total(), 80);
+        assert_eq!(lag.by_topic().get("one"), Some(&80));
+
+        // a listed partition without any commit still shows up
+        let lag = GroupLag::new("g1", &[], &[offset(2, 5)], &[offset(2, 25)]);
+        assert_eq!(lag.partitions[0].partition, 2);
+        assert_eq!(lag.total(), 20);
+
+        let instance = ConsumerInstance {
+            name: "c1".to_string(),
+            topics: vec![Topic {
+                topic: "one".to_string(),
+            }],
+        };
+        let counts: PartitionCounts = [("one".to_string(), 2)].into_iter().collect();
+        assert_eq!(
+            instance.partitions(&counts),
+            (
+                vec![TopicPartition::new("one", 0), TopicPartition::new("one", 1)],
+                vec![]
+            )
+        );
+        assert_eq!(
+            instance.partitions(&PartitionCounts::new()),
+            (vec![], vec!["one".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn lag_unknown_topics() {
+        // cargo test -p upstash --lib -- tests::lag_unknown_topics --exact --nocapture
+
+        let (client, requests) = mock_server(|_, path| {
+            let response = match path {
+                "/consumers" => {
+                    r#"[
+                        {"name": "g1", "instances": [{"name": "c1", "topics": [{"topic": "one"}, {"topic": "two"}]}]},
+                        {"name": "g2", "instances": [{"name": "c1", "topics": [{"topic": "two"}]}]}
+                    ]"#
+                }
+                "/committed/g1/c1" => r#"[{"topic": "one", "partition": 0, "offset": 5}]"#,
+                "/offsets/earliest" => r#"[{"topic": "one", "partition": 0, "offset": 0}]"#,
+                "/offsets/latest" => r#"[{"topic": "one", "partition": 0, "offset": 10}]"#,
+                _ => "[]",
+            };
+            response.to_string()
+        });
+        let counts: PartitionCounts = [("one".to_string(), 1)].into_iter().collect();
+
+        let lags = client.handler("").lag(&counts).await.unwrap();
+        assert_eq!(lags.len(), 2);
+        assert_eq!(lags[0].total(), 5);
+        assert_eq!(lags[0].unknown_topics, vec!["two"]);
+        assert!(lags[1].partitions.is_empty());
+        assert_eq!(lags[1].unknown_topics, vec!["two"]);
+        let listed = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, path, _)| path == "/consumers")
+            .count();
+        assert_eq!(listed, 1);
+    }
+
+    #[tokio::test]
+    async fn lag_monitor() {
+        // cargo test -p upstash --lib -- tests::lag_monitor --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let partitions: PartitionCounts = [("one".to_string(), 1)].into_iter().collect();
+        println!("Upstash Kafka Lag:\n{:#?}", client.handler("").lag(&partitions).await);
+
+        let config = LagMonitorConfig {
+            partitions,
+            ..Default::default()
+        };
+        let monitor = LagMonitor::new(client.clone(), config)
+            .on_report(|lags| println!("Upstash Kafka Lag Report:\n{:#?}", lags))
+            .on_threshold(LagThreshold::new(100).group("g1"), |alert| {
+                println!("Upstash Kafka Lag Alert:\n{:#?}", alert)
+            });
+        let stop = monitor.stop_handle();
+        let task = tokio::spawn(async move { monitor.run().await });
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        stop.stop();
+        println!("Upstash Kafka Lag Monitor:\n{:#?}", task.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reset_offsets() {
+        // cargo test -p upstash --lib -- tests::reset_offsets --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let partitions = vec![TopicPartition::new("one", 0)];
+        let response = client
+            .handler("")
+            .reset_offsets("g1", "c1", partitions, ResetTarget::Earliest, true)
+            .await;
+        println!("Upstash Kafka Offset Reset Plan:\n{:#?}", &response);
+    }
+
+    #[tokio::test]
+    async fn reset_offsets_requires_partitions() {
+        // cargo test -p upstash --lib -- tests::reset_offsets_requires_partitions --exact --nocapture
+
+        let client = Client::kafka_from("localhost", "user", "password");
+        let err = client
+            .handler("")
+            .reset_offsets("g1", "c1", vec![], ResetTarget::Earliest, true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+    }
+
+    #[tokio::test]
+    async fn reset_offsets_out_of_range() {
+        // cargo test -p upstash --lib -- tests::reset_offsets_out_of_range --exact --nocapture
+
+        let (client, requests) = mock_server(|_, path| {
+            let response = match path {
+                "/offsets/earliest" => r#"[{"topic": "one", "partition": 0, "offset": 10}]"#,
+                "/offsets/latest" => r#"[{"topic": "one", "partition": 0, "offset": 20}]"#,
+                _ if path.starts_with("/commit/") => r#"{"result": "ok", "error": "", "status": 200}"#,
+                _ => "[]",
+            };
+            response.to_string()
+        });
+        let handler = client.handler("");
+        let partitions = vec![TopicPartition::new("one", 0)];
+
+        for offset in [5, 21] {
+            let err = handler
+                .reset_offsets("g1", "c1", partitions.clone(), ResetTarget::Offset(offset), false)
+                .await
+                .unwrap_err();
+            assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+            assert!(err.to_string().contains("from 10 to 20"));
+        }
+        assert!(commits(&requests).is_empty());
+
+        let plan = handler
+            .reset_offsets("g1", "c1", partitions, ResetTarget::Offset(20), false)
+            .await
+            .unwrap();
+        assert!(plan.applied);
+        assert_eq!(
+            commits(&requests),
+            vec![r#"[{"topic":"one","partition":0,"offset":20}]"#]
+        );
+    }
+
+    #[tokio::test]
+    async fn partition_offsets() {
+        // cargo test -p upstash --lib -- tests::partition_offsets --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let handler = client.handler("");
+        for at in [
+            OffsetSpec::Earliest,
+            OffsetSpec::Latest,
+            OffsetSpec::Timestamp(1657000000000),
+        ] {
+            let response = handler.partition_offsets(at, vec![TopicPartition::new("one", 0)]).await;
+            println!("Upstash Kafka Partition Offsets {:?}:\n{:#?}", at, &response);
+        }
+    }
+
+    #[test]
+    fn commit_from_records() {
+        // cargo test -p upstash --lib -- tests::commit_from_records --exact --nocapture
+
+        let record = |topic: &str, partition: u64, offset: u64| ConsumeResponse {
+            key: "key".to_string(),
+            offset,
+            partition,
+            timestamp: 0,
+            topic: topic.to_string(),
+            value: "21".to_string(),
+            headers: Vec::new(),
+            invalid: None,
+        };
+        let records = vec![
+            record("one", 0, 106),
+            record("one", 0, 108),
+            record("one", 0, 107),
+            record("crab", 1, 3),
+            record("crab", 300, 5),
+        ];
+
+        let req = CommitRequest::from_records(&records);
+        assert_eq!(req.len(), 3);
+        assert_eq!((req[1].partition, req[1].offset), (300, 6));
+        assert_eq!((req[0].topic.as_str(), req[0].partition, req[0].offset), ("crab", 1, 4));
+        assert_eq!(
+            (req[2].topic.as_str(), req[2].partition, req[2].offset),
+            ("one", 0, 109)
+        );
+    }
+
+    #[test]
+    fn dead_letter_headers() {
+        // cargo test -p upstash --lib -- tests::dead_letter_headers --exact --nocapture
+
+        let record = ConsumeResponse {
+            key: "key".to_string(),
+            offset: 108,
+            partition: 0,
+            timestamp: 0,
+            topic: "one".to_string(),
+            value: "21".to_string(),
+            headers: vec![Header::new("source", "billing")],
+            invalid: None,
+        };
+        let err = error::Error::new("handler failed", error::ErrorKind::Internal);
+
+        let message = dead_letter_message("one-dlq", &record, &err, 3);
+        assert_eq!(message.topic, "one-dlq");
+        assert_eq!((message.key.as_str(), message.value.as_str()), ("key", "21"));
+        assert_eq!(message.header("source"), Some("billing"));
+        assert_eq!(message.header(DLQ_ERROR_HEADER), Some("handler failed"));
+        assert_eq!(message.header(DLQ_ATTEMPTS_HEADER), Some("3"));
+        assert_eq!(message.header(DLQ_SOURCE_TOPIC_HEADER), Some("one"));
+        assert_eq!(message.header(DLQ_SOURCE_OFFSET_HEADER), Some("108"));
+    }
+
+    #[test]
+    fn retry_topics_message() {
+        // cargo test -p upstash --lib -- tests::retry_topics_message --exact --nocapture
+
+        let mut record = ConsumeResponse {
+            key: "key".to_string(),
+            offset: 108,
+            partition: 0,
+            timestamp: 0,
+            topic: "one".to_string(),
+            value: "21".to_string(),
+            headers: vec![Header::new("source", "billing"), Header::new("retry-policy", "backoff")],
+            invalid: None,
+        };
+        let retry_topics = RetryTopics::new(vec![
+            RetryTier::new("one-retry-1m", std::time::Duration::from_secs(60)),
+            RetryTier::new("one-retry-10m", std::time::Duration::from_secs(600)),
+        ]);
+        let dead_letter = DeadLetterPolicy::new("one-dlq", 3);
+        let err = error::Error::new("handler failed", error::ErrorKind::Internal);
+
+        let first = retry_topics.next_message(&record, &err, Some(&dead_letter)).unwrap();
+        assert_eq!(first.topic, "one-retry-1m");
+        assert_eq!(first.header("source"), Some("billing"));
+        assert_eq!(first.header(RETRY_ATTEMPT_HEADER), Some("1"));
+        assert_eq!(first.header(RETRY_SOURCE_TOPIC_HEADER), Some("one"));
+
+        record.topic = first.topic.clone();
+        record.headers = first.headers.clone();
+        assert!(RetryTopics::due_in(&record) > std::time::Duration::from_secs(55));
+        let second = retry_topics.next_message(&record, &err, Some(&dead_letter)).unwrap();
+        assert_eq!(second.topic, "one-retry-10m");
+        assert_eq!(second.header(RETRY_ATTEMPT_HEADER), Some("2"));
+        assert_eq!(second.header(RETRY_SOURCE_TOPIC_HEADER), Some("one"));
+        assert_eq!(second.header("retry-policy"), Some("backoff"));
+        assert_eq!(
+            second.headers.iter().filter(|h| h.key == RETRY_ATTEMPT_HEADER).count(),
+            1
+        );
+
+        record.topic = second.topic.clone();
+        record.headers = second.headers.clone();
+        let dead = retry_topics.next_message(&record, &err, Some(&dead_letter)).unwrap();
+        assert_eq!(dead.topic, "one-dlq");
+        assert_eq!(dead.header(DLQ_ATTEMPTS_HEADER), Some("3"));
+        assert!(retry_topics.next_message(&record, &err, None).is_none());
+    }
+
+    type Requests = Arc<std::sync::Mutex<Vec<(String, String, String)>>>;
+
+    /// Serves the json returned by `respond` for each method and path on a local port,
+    /// recording every request as method, path and body.
+    fn mock_server(respond: impl Fn(&str, &str) -> String + Send + 'static) -> (Client, Requests) {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = Client::kafka_from("localhost", "user", "password");
+        client.base_url = url::Url::parse(&format!("http://user:password@{}", listener.local_addr().unwrap())).unwrap();
+        let requests = Requests::default();
+        let seen = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = respond(&method, &path);
+                seen.lock()
+                    .unwrap()
+                    .push((method, path, String::from_utf8(body).unwrap()));
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+        (client, requests)
+    }
+
+    /// A mock server handing out the batches to consume in order and then empty ones,
+    /// accepting every commit, produce and consumer deletion.
+    fn mock_consumer(batches: Vec<String>) -> (Client, Requests) {
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        mock_server(move |_, path| {
+            if path.starts_with("/consume") {
+                let poll = polls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                return batches.get(poll).cloned().unwrap_or_else(|| "[]".to_string());
+            }
+            if path == "/produce" {
+                return r#"[{"topic": "one-dlq", "partition": 0, "offset": 0}]"#.to_string();
+            }
+            r#"{"result": "ok", "error": "", "status": 200}"#.to_string()
+        })
+    }
+
+    /// A consumed batch of topic one, as partition, offset and value.
+    fn consumed(records: &[(u64, u64, &str)]) -> String {
+        let records: Vec<serde_json::Value> = records
+            .iter()
+            .map(|(partition, offset, value)| {
+                serde_json::json!({
+                    "topic": "one", "partition": partition, "offset": offset, "timestamp": 0, "key": "", "value": value,
+                })
+            })
+            .collect();
+        serde_json::Value::from(records).to_string()
+    }
+
+    fn commits(requests: &Requests) -> Vec<String> {
+        let requests = requests.lock().unwrap();
+        requests
+            .iter()
+            .filter(|(_, path, _)| path.starts_with("/commit/"))
+            .map(|(_, _, body)| body.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn consumer_runner_commits() {
+        // cargo test -p upstash --lib -- tests::consumer_runner_commits --exact --nocapture
+
+        let batches = || {
+            vec![
+                consumed(&[(0, 0, "21"), (1, 5, "21")]),
+                consumed(&[(0, 1, "fail"), (0, 2, "21")]),
+            ]
+        };
+        let config = RunnerConfig {
+            idle_backoff: std::time::Duration::from_millis(10),
+            ..Default::default()
+        };
+
+        // the handler fails on the second batch: what succeeded before is committed, the instance is deleted
+        // and the error returned, the failed record and the one after it are not committed
+        let (client, requests) = mock_consumer(batches());
+        let runner = ConsumerRunner::new(client, "g1", "c1", ConsumeRequest::new("one"), config.clone());
+        let handled = std::sync::Mutex::new(Vec::new());
+        let err = runner
+            .run(|record: ConsumeResponse| {
+                handled.lock().unwrap().push((record.partition, record.offset));
+                let result = match record.value.as_str() {
+                    "fail" => Err(Error::new("handler failed", ErrorKind::Internal)),
+                    _ => Ok(()),
+                };
+                async { result }
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::Internal));
+        assert_eq!(*handled.lock().unwrap(), vec![(0, 0), (1, 5), (0, 1)]);
+        assert_eq!(
+            commits(&requests),
+            vec![r#"[{"topic":"one","partition":0,"offset":1},{"topic":"one","partition":1,"offset":6}]"#]
+        );
+        let paths: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, path, _)| path.clone())
+            .collect();
+        let commit = paths.iter().position(|path| path.starts_with("/commit/")).unwrap();
+        let delete = paths
+            .iter()
+            .position(|path| path.starts_with("/delete-consumer/g1/c1"))
+            .unwrap();
+        assert!(commit < delete);
+
+        // a failing batch commits nothing of that batch
+        let (client, requests) = mock_consumer(batches());
+        let runner = ConsumerRunner::new(client, "g1", "c1", ConsumeRequest::new("one"), config.clone());
+        let err = runner.run_batch(FailingBatch).await.unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::Internal));
+        assert_eq!(
+            commits(&requests),
+            vec![r#"[{"topic":"one","partition":0,"offset":1},{"topic":"one","partition":1,"offset":6}]"#]
+        );
+
+        // once everything succeeded, the highest offsets are committed when the runner stops
+        let (client, requests) = mock_consumer(batches());
+        let runner = ConsumerRunner::new(client, "g1", "c1", ConsumeRequest::new("one"), config);
+        let stop = runner.stop_handle();
+        runner
+            .run(|record: ConsumeResponse| {
+                if record.offset == 2 {
+                    stop.stop();
+                }
+                async { Ok(()) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            commits(&requests),
+            vec![r#"[{"topic":"one","partition":0,"offset":3},{"topic":"one","partition":1,"offset":6}]"#]
+        );
+        assert!(!requests.lock().unwrap().iter().any(|(method, _, _)| method == "DELETE"));
+    }
+
+    struct FailingBatch;
+
+    #[async_trait::async_trait]
+    impl BatchHandler for FailingBatch {
+        async fn handle_batch(&self, records: &[ConsumeResponse]) -> Result<()> {
+            match records.iter().any(|record| record.value == "fail") {
+                true => Err(Error::new("handler failed", ErrorKind::Internal)),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn consumer_runner() {
+        // cargo test -p upstash --lib -- tests::consumer_runner --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let runner = ConsumerRunner::new(
+            client.clone(),
+            "g1",
+            "c1",
+            ConsumeRequest::new("one"),
+            RunnerConfig::default(),
+        );
+        let stop = runner.stop_handle();
+        let task = tokio::spawn(async move {
+            runner
+                .run(|record: ConsumeResponse| async move {
+                    println!("Upstash Kafka Consumer Runner:\n{:#?}", &record);
+                    Ok(())
+                })
+                .await
+        });
+
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        stop.stop();
+        println!("Upstash Kafka Consumer Runner Result:\n{:#?}", task.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn dedup_filter() {
+        // cargo test -p upstash --lib -- tests::dedup_filter --exact --nocapture
+
+        let client = Client::kafka_from("localhost", "user", "password");
+        let producer = IdempotentProducer::with_producer_id(client, "p1");
+        let record = |message: Message| ConsumeResponse {
+            key: message.key,
+            offset: 0,
+            partition: 0,
+            timestamp: 0,
+            topic: message.topic,
+            value: message.value,
+            headers: message.headers,
+            invalid: None,
+        };
+        let first = record(producer.stamp(Message::new("one", "21", None, Some("key"))));
+        let second = record(producer.stamp(Message::new("one", "22", None, Some("key"))));
+        let third = record(producer.stamp(Message::new("one", "23", None, Some("key"))));
+        assert_eq!(
+            second
+                .headers
+                .iter()
+                .find(|h| h.key == PRODUCER_SEQUENCE_HEADER)
+                .unwrap()
+                .value,
+            "1"
+        );
+
+        let filter = DedupFilter::new(WindowStore::new(2));
+        let records = vec![first.clone(), second.clone(), first.clone(), third.clone()];
+        let unique = filter.filter(records).await.unwrap();
+        assert_eq!(
+            unique.iter().map(|r| r.value.as_str()).collect::<Vec<_>>(),
+            ["21", "22", "23"]
+        );
+        // the first sequence fell out of the window and is forgotten, not dropped
+        assert!(!filter.is_duplicate(&first).await.unwrap());
+        assert!(filter.is_duplicate(&third).await.unwrap());
+
+        // sequences spread across partitions are consumed out of order
+        let fourth = record(producer.stamp(Message::new("one", "24", Some(0), Some("key"))));
+        let fifth = record(producer.stamp(Message::new("one", "25", Some(1), Some("key"))));
+        let unique = filter.filter(vec![fifth, fourth]).await.unwrap();
+        assert_eq!(
+            unique.iter().map(|r| r.value.as_str()).collect::<Vec<_>>(),
+            ["25", "24"]
+        );
+        assert!(!filter
+            .is_duplicate(&record(Message::new("one", "24", None, Some("key"))))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn window_store_producers() {
+        // cargo test -p upstash --lib -- tests::window_store_producers --exact --nocapture
+
+        let store = WindowStore::new(10).with_max_producers(2);
+        store.insert("p1", 1).await.unwrap();
+        store.insert("p2", 1).await.unwrap();
+        store.insert("p1", 2).await.unwrap();
+        // p2 was inserted least recently, so the third producer evicts it
+        store.insert("p3", 1).await.unwrap();
+        assert!(!store.contains("p2", 1).await.unwrap());
+        assert!(store.contains("p1", 1).await.unwrap());
+        assert!(store.contains("p3", 1).await.unwrap());
+
+        store.insert("p2", 2).await.unwrap();
+        assert!(!store.contains("p1", 2).await.unwrap());
+        assert!(store.contains("p3", 1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn memory_outbox() {
+        // cargo test -p upstash --lib -- tests::memory_outbox --exact --nocapture
+
+        let outbox = MemoryOutbox::new();
+        let first = outbox.add(Message::new("one", "21", None, Some("key")));
+        let second = outbox.add(Message::new("one", "22", None, Some("key")));
+        let err = error::Error::new("produce failed", error::ErrorKind::Internal);
+        let response = ProduceResponse {
+            topic: "one".to_string(),
+            partition: 0,
+            offset: 108,
+        };
+
+        outbox.mark_sent(first, &response).await.unwrap();
+        outbox.mark_failed(second, &err).await.unwrap();
+        assert_eq!(outbox.sent(first).unwrap().offset, 108);
+        assert_eq!(outbox.last_error(second).as_deref(), Some("produce failed"));
+
+        let pending = outbox.pending(10, 2).await.unwrap();
+        assert_eq!(
+            pending.iter().map(|e| (e.id, e.attempts)).collect::<Vec<_>>(),
+            [(second, 1)]
+        );
+        outbox.mark_failed(second, &err).await.unwrap();
+        assert!(outbox.pending(10, 2).await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_outbox() {
+        // cargo test -p upstash --features sqlite --lib -- tests::sqlite_outbox --exact --nocapture
+
+        let outbox = SqliteOutbox::new(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        let (first, second) = outbox
+            .transaction(|tx| {
+                tx.execute_batch("CREATE TABLE orders (id INTEGER); INSERT INTO orders VALUES (1);")
+                    .unwrap();
+                let message = Message::new("one", "21", Some(2), Some("key")).with_header("source", "billing");
+                let first = SqliteOutbox::enqueue(tx, &message)?;
+                let second = SqliteOutbox::enqueue(tx, &Message::new("one", "22", None, Some("key")))?;
+                Ok((first, second))
+            })
+            .unwrap();
+
+        let pending = outbox.pending(10, 3).await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].message.partition, 2);
+        assert_eq!(pending[0].message.header("source"), Some("billing"));
+
+        let response = ProduceResponse {
+            topic: "one".to_string(),
+            partition: 2,
+            offset: 108,
+        };
+        outbox.mark_sent(first, &response).await.unwrap();
+        let err = error::Error::new("produce failed", error::ErrorKind::Internal);
+        outbox.mark_failed(second, &err).await.unwrap();
+        let pending = outbox.pending(10, 3).await.unwrap();
+        assert_eq!(
+            pending.iter().map(|e| (e.id, e.attempts)).collect::<Vec<_>>(),
+            [(second, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn outbox_relay() {
+        // cargo test -p upstash --lib -- tests::outbox_relay --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let client = Client::kafka_instance().unwrap();
+
+        let outbox = MemoryOutbox::new();
+        let id = outbox.add(Message::new("one", "21", None, Some("key")));
+        let relay = OutboxRelay::new(client.clone(), outbox, OutboxConfig::default());
+        let response = relay.relay_once().await;
+        println!("Upstash Kafka Outbox Relay:\n{:#?}", &response);
+        println!("Upstash Kafka Outbox Sent:\n{:#?}", relay.store().sent(id));
+    }
+
+    #[cfg(feature = "webhook")]
+    #[tokio::test]
+    async fn webhook_receiver() {
+        // cargo test -p upstash --features webhook --lib -- tests::webhook_receiver --exact --nocapture
+
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use base64::Engine;
+        use hmac::{Hmac, KeyInit, Mac};
+        use sha2::{Digest, Sha256};
+        use std::sync::Mutex;
+
+        struct Collect(Mutex<Vec<String>>);
+        #[async_trait::async_trait]
+        impl BatchHandler for Collect {
+            async fn handle_batch(&self, records: &[ConsumeResponse]) -> error::Result<()> {
+                let mut values = self.0.lock().unwrap();
+                values.extend(records.iter().map(|r| r.value.clone()));
+                Ok(())
+            }
+        }
+
+        let sign = |key: &str, body: &[u8]| {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+            let claims = serde_json::json!({
+                "iss": "Upstash",
+                "sub": "https://example.com/webhook",
+                "exp": now + 300,
+                "nbf": now,
+                "body": URL_SAFE_NO_PAD.encode(Sha256::digest(body)),
+            });
+            let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+            mac.update(format!("{}.{}", header, payload).as_bytes());
+            let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+            format!("{}.{}.{}", header, payload, signature)
+        };
+
+        let body = br#"[{"topic":"one","partition":0,"offset":108,"timestamp":0,"key":"key","value":"21"}]"#;
+        let verifier = WebhookVerifier::new("current", "next");
+        let receiver =
+            WebhookReceiver::new(Collect(Mutex::new(Vec::new())), verifier).with_url("https://example.com/webhook");
+
+        assert_eq!(receiver.receive(Some(&sign("next", body)), body).await.status, 200);
+        assert_eq!(receiver.receive(Some(&sign("other", body)), body).await.status, 401);
+        assert_eq!(receiver.receive(None, body).await.status, 401);
+        let tampered = br#"[{"topic":"one","partition":0,"offset":108,"timestamp":0,"key":"key","value":"22"}]"#;
+        assert_eq!(
+            receiver.receive(Some(&sign("current", body)), tampered).await.status,
+            401
+        );
+        assert_eq!(
+            receiver.receive(Some(&sign("current", b"nope")), b"nope").await.status,
+            400
+        );
+
+        let single = br#"{"topic":"one","partition":0,"offset":109,"timestamp":0,"key":"key","value":"23"}"#;
+        assert_eq!(
+            receiver.receive(Some(&sign("current", single)), single).await.status,
+            200
+        );
+        assert_eq!(*receiver.handler().0.lock().unwrap(), ["21", "23"]);
+
+        let insecure = WebhookReceiver::insecure_without_verification(Collect(Mutex::new(Vec::new())));
+        assert_eq!(insecure.receive(None, body).await.status, 200);
+    }
+
+    #[tokio::test]
+    async fn mirror() {
+        // cargo test -p upstash --lib -- tests::mirror --exact --nocapture
+
+        dotenv::dotenv().unwrap();
+        Client::kafka_env().initialize();
+        let source = Client::kafka_instance().unwrap();
+        let target = source.clone();
+
+        let config = MirrorConfig {
+            topics: [("one".to_string(), "two".to_string())].into_iter().collect(),
+            ..Default::default()
+        };
+        let mirror = Mirror::new(
+            source.clone(),
+            target,
+            "mirror",
+            "m1",
+            ConsumeRequest::new("one"),
+            config,
+        );
+        let stop = mirror.stop_handle();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            stop.stop();
+        });
+        let response = mirror.run().await;
+        println!("Upstash Kafka Mirror {} records:\n{:#?}", mirror.mirrored(), &response);
+        let partitions: PartitionCounts = [("one".to_string(), 1)].into_iter().collect();
+        println!("Upstash Kafka Mirror Lag:\n{:#?}", mirror.lag(&partitions).await);
+    }
+
     #[tokio::test]
     async fn list_consumers() {
         // cargo test -p upstash --lib -- tests::list_consumers --exact --nocapture
@@ -566,4 +2004,252 @@ mod tests {
         let response = client.handler("").delete_consumer(group, consumer).await;
         println!("Upstash Kafka Delete Consumer:\n{:#?}", &response);
     }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn schema_registry_validate() {
+        // cargo test -p upstash --lib --features schema -- tests::schema_registry_validate --exact --nocapture
+
+        let registry = SchemaRegistry::default();
+        let schema = r#"{
+            "type": "object",
+            "properties": { "id": { "type": "integer" }, "name": { "type": "string" } },
+            "required": ["id"]
+        }"#;
+        registry.register("one", SchemaFormat::JsonSchema, schema).unwrap();
+
+        assert!(registry.validate("one", r#"{"id": 1, "name": "crab"}"#).is_ok());
+        assert!(registry.validate("one", r#"{"name": "crab"}"#).is_err());
+        assert!(registry.validate("one", "not json").is_err());
+        assert!(registry.validate("two", "anything goes").is_ok());
+
+        let avro = r#"{
+            "type": "record",
+            "name": "Event",
+            "fields": [{ "name": "id", "type": "long" }, { "name": "name", "type": "string" }]
+        }"#;
+        registry.register("events", SchemaFormat::Avro, avro).unwrap();
+        assert!(registry.validate("events", r#"{"id": 1, "name": "crab"}"#).is_ok());
+        assert!(registry.validate("events", r#"{"id": "1"}"#).is_err());
+    }
+
+    #[cfg(feature = "schema")]
+    #[tokio::test]
+    async fn schema_produce_report() {
+        // cargo test -p upstash --lib --features schema -- tests::schema_produce_report --exact --nocapture
+
+        let registry = SchemaRegistry::default();
+        let schema = r#"{ "type": "object", "required": ["id"] }"#;
+        registry.register("one", SchemaFormat::JsonSchema, schema).unwrap();
+        let client = Client::kafka_from("localhost", "user", "password").with_schema_registry(registry);
+
+        // every message is rejected before sending, so nothing goes over the network
+        let report = client
+            .producer()
+            .produce_report(vec![Message::new("one", "{}", Some(0), Some("key"))])
+            .await
+            .unwrap();
+        assert_eq!(report.failed().count(), 1);
+    }
+
+    #[cfg(feature = "schema")]
+    #[tokio::test]
+    async fn schema_consumer_runner() {
+        // cargo test -p upstash --lib --features schema -- tests::schema_consumer_runner --exact --nocapture
+
+        let server = || {
+            let batch = consumed(&[(0, 0, r#"{"id": 1}"#), (0, 1, "{}"), (0, 2, r#"{"id": 3}"#)]);
+            let (client, requests) = mock_consumer(vec![batch]);
+            let registry = SchemaRegistry::default();
+            let schema = r#"{ "type": "object", "required": ["id"] }"#;
+            registry.register("one", SchemaFormat::JsonSchema, schema).unwrap();
+            (client.with_schema_registry(registry), requests)
+        };
+        let config = RunnerConfig {
+            idle_backoff: std::time::Duration::from_millis(10),
+            ..Default::default()
+        };
+
+        // without a dead-letter topic the runner stops at the invalid record, committing only what came before it
+        let (client, requests) = server();
+        let runner = ConsumerRunner::new(client, "g1", "c1", ConsumeRequest::new("one"), config.clone());
+        let handled = std::sync::Mutex::new(Vec::new());
+        let err = runner
+            .run(|record: ConsumeResponse| {
+                handled.lock().unwrap().push(record.offset);
+                async { Ok(()) }
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+        assert_eq!(*handled.lock().unwrap(), vec![0]);
+        assert_eq!(
+            commits(&requests),
+            vec![r#"[{"topic":"one","partition":0,"offset":1}]"#]
+        );
+        assert!(requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(method, path, _)| method == "DELETE" && path.starts_with("/delete-consumer/g1/c1")));
+
+        // with one it is dead-lettered and the records around it are still handled
+        let (client, requests) = server();
+        let config = RunnerConfig {
+            dead_letter: Some(DeadLetterPolicy::new("one-dlq", 3)),
+            ..config
+        };
+        let runner = ConsumerRunner::new(client, "g1", "c1", ConsumeRequest::new("one"), config);
+        let stop = runner.stop_handle();
+        let handled = std::sync::Mutex::new(Vec::new());
+        runner
+            .run(|record: ConsumeResponse| {
+                handled.lock().unwrap().push(record.offset);
+                if record.offset == 2 {
+                    stop.stop();
+                }
+                async { Ok(()) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(*handled.lock().unwrap(), vec![0, 2]);
+        assert_eq!(
+            commits(&requests),
+            vec![r#"[{"topic":"one","partition":0,"offset":3}]"#]
+        );
+        let requests = requests.lock().unwrap();
+        let (_, _, produced) = requests.iter().find(|(_, path, _)| path == "/produce").unwrap();
+        assert!(produced.contains("one-dlq") && produced.contains(DLQ_ERROR_HEADER));
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn schema_registry_compatibility() {
+        // cargo test -p upstash --lib --features schema -- tests::schema_registry_compatibility --exact --nocapture
+
+        let registry = SchemaRegistry::new(Compatibility::Backward);
+        let v1 = r#"{"type": "object", "properties": {"id": {"type": "integer"}}, "required": ["id"]}"#;
+        let v2 = r#"{"type": "object", "properties": {"id": {"type": "number"}, "tag": {"type": "string"}}, "required": ["id"]}"#;
+        let v3 = r#"{"type": "object", "properties": {"id": {"type": "number"}, "tag": {"type": "string"}}, "required": ["id", "tag"]}"#;
+
+        assert_eq!(registry.register("one", SchemaFormat::JsonSchema, v1).unwrap(), 1);
+        assert_eq!(registry.register("one", SchemaFormat::JsonSchema, v2).unwrap(), 2);
+        assert_eq!(registry.register("one", SchemaFormat::JsonSchema, v2).unwrap(), 2);
+        assert!(registry.register("one", SchemaFormat::JsonSchema, v3).is_err());
+        assert!(check_compatibility(SchemaFormat::JsonSchema, v3, v2, Compatibility::Backward).is_ok());
+        assert!(check_compatibility(SchemaFormat::JsonSchema, v1, v2, Compatibility::Forward).is_err());
+        assert_eq!(registry.versions("one"), vec![1, 2]);
+
+        let a1 = r#"{"type": "record", "name": "Event", "fields": [{"name": "id", "type": "long"}]}"#;
+        let a2 = r#"{"type": "record", "name": "Event", "fields": [{"name": "id", "type": "long"}, {"name": "tag", "type": "string", "default": ""}]}"#;
+        let a3 = r#"{"type": "record", "name": "Event", "fields": [{"name": "id", "type": "long"}, {"name": "tag", "type": "string"}]}"#;
+        assert!(check_compatibility(SchemaFormat::Avro, a1, a2, Compatibility::Full).is_ok());
+        assert!(check_compatibility(SchemaFormat::Avro, a1, a3, Compatibility::Backward).is_err());
+        assert!(check_compatibility(SchemaFormat::Avro, a1, a3, Compatibility::Forward).is_ok());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compression_roundtrip() {
+        // cargo test -p upstash --lib --features compression -- tests::compression_roundtrip --exact --nocapture
+
+        let value = "implrust ".repeat(500);
+        for codec in [Codec::Gzip, Codec::Zstd, Codec::Lz4] {
+            let message = Message::new("one", value.clone(), Some(0), Some("key"));
+            let compressed = Compression::new(codec).compress_message(&message).unwrap();
+            assert_eq!(compressed.header(COMPRESSION_HEADER), Some(codec.name()));
+            assert!(compressed.value.len() < value.len());
+
+            let (mut headers, mut restored) = (compressed.headers, compressed.value);
+            decompress_value(&mut headers, &mut restored).unwrap();
+            assert_eq!(restored, value);
+            assert!(headers.is_empty());
+        }
+
+        let small = Message::new("one", "21", Some(0), Some("key"));
+        let unchanged = Compression::new(Codec::Zstd).compress_message(&small).unwrap();
+        assert_eq!(unchanged.value, "21");
+        assert!(unchanged.headers.is_empty());
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn compression_invalid_record() {
+        // cargo test -p upstash --lib --features compression -- tests::compression_invalid_record --exact --nocapture
+
+        let message = Message::new("one", "implrust ".repeat(500), Some(0), Some("key"));
+        let compressed = Compression::new(Codec::Zstd).compress_message(&message).unwrap();
+        let record = |offset: u64, value: &str, codec: &str| {
+            serde_json::json!({
+                "topic": "one", "partition": 0, "offset": offset, "timestamp": 0, "key": "key", "value": value,
+                "headers": [{"key": COMPRESSION_HEADER, "value": codec}],
+            })
+        };
+        let records = serde_json::json!([
+            record(0, &compressed.value, "zstd"),
+            record(1, "not base64", "zstd"),
+            record(2, &compressed.value, "snappy"),
+            record(3, &compressed.value, "zstd"),
+        ])
+        .to_string();
+        let (client, _) = mock_server(move |_, _| records.clone());
+
+        let fetched = client.fetcher().fetch(FetchRequest::new("one", 0, 0)).await.unwrap();
+        assert_eq!(fetched.len(), 4);
+        assert_eq!(fetched[0].value, message.value);
+        assert!(fetched[0].check().is_ok() && fetched[3].check().is_ok());
+        let err = fetched[1].check().unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+        assert!(fetched[2].check().is_err());
+        // a record which cannot be decompressed is left as received
+        assert_eq!(fetched[2].value, compressed.value);
+        assert_eq!(fetched[2].headers.len(), 1);
+
+        let consumed = client
+            .consumer()
+            .consume("g1", "c1", ConsumeRequest::new("one"), ConsumeOptions::default())
+            .await
+            .unwrap();
+        let invalid: Vec<u64> = consumed
+            .iter()
+            .filter(|r| r.check().is_err())
+            .map(|r| r.offset)
+            .collect();
+        assert_eq!(invalid, vec![1, 2]);
+        assert_eq!(consumed[3].value, message.value);
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn batching_producer_compressed_size() {
+        // cargo test -p upstash --lib --features compression -- tests::batching_producer_compressed_size --exact --nocapture
+
+        let config = BatchConfig {
+            max_message_size: Some(1000),
+            ..Default::default()
+        };
+        let message = Message::new("one", "implrust ".repeat(500), Some(0), Some("key"));
+        let produced = || r#"[{"topic":"one","partition":0,"offset":0}]"#.to_string();
+
+        let (client, requests) = mock_server(move |_, _| produced());
+        let producer = BatchingProducer::new(client, config.clone());
+        let delivery = producer.send(message.clone());
+        producer.shutdown().await;
+        let err = delivery.await.unwrap_err();
+        assert!(matches!(err.kind(), error::ErrorKind::InvalidData));
+        assert!(requests.lock().unwrap().is_empty());
+
+        // the size is taken after compression, so the same message fits once the client compresses it
+        let (client, requests) = mock_server(move |_, _| produced());
+        let producer = BatchingProducer::new(client.with_compression(Compression::new(Codec::Zstd)), config);
+        let delivery = producer.send(message);
+        producer.shutdown().await;
+        assert_eq!(delivery.await.unwrap().offset, 0);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        // the message is compressed once
+        let sent: Vec<Message> = serde_json::from_str(&requests[0].2).unwrap();
+        assert_eq!(sent[0].headers.len(), 1);
+        assert!(sent[0].value.len() < 1000);
+    }
 }